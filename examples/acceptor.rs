@@ -0,0 +1,71 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, sleep, timeout};
+
+pub struct Lines(BufReader<OwnedReadHalf>);
+
+impl Poll for Lines {
+    type Item = String;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            let mut line = String::new();
+            anyhow::ensure!(self.0.read_line(&mut line).await? > 0, "client hung up");
+
+            tx.send(line).await?;
+        }
+    }
+}
+
+pub struct Reply(OwnedWriteHalf);
+
+impl Push<String> for Reply {
+    async fn push(&mut self, line: String) -> anyhow::Result<()> {
+        self.0.write_all(line.to_uppercase().as_bytes()).await?;
+        Ok(())
+    }
+}
+
+async fn client(name: &str, lines: usize) -> anyhow::Result<()> {
+    let (r, mut w) = TcpStream::connect("127.0.0.1:7879").await?.into_split();
+    let mut r = BufReader::new(r);
+
+    for i in 0..lines {
+        w.write_all(format!("{name} says hi #{i}\n").as_bytes()).await?;
+
+        let mut reply = String::new();
+        r.read_line(&mut reply).await?;
+        print!("{name} got {reply}");
+
+        sleep(Duration::from_millis(100)).await;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    // every client gets its own reader and writer stages
+    let acceptor = TcpAcceptor::bind("127.0.0.1:7879", |stream, _peer| {
+        let (r, w) = stream.into_split();
+
+        let (lines, rx) = poll(Lines(BufReader::new(r)));
+        let reply = push(rx).to(Reply(w));
+
+        all!(lines, reply)
+    });
+
+    let (acceptor, rx) = poll(acceptor.max_connections(16));
+    let log = push(rx).to_fn(|closed: Closed| println!("{} left: {:?}", closed.peer, closed.error));
+
+    let clients = async {
+        sleep(Duration::from_millis(100)).await;
+        let _ = futures::join!(client("ann", 2), client("bob", 5), client("cy", 3));
+        sleep(Duration::from_millis(100)).await;
+    };
+
+    let _ = timeout(Duration::from_secs(3), futures::future::select(Box::pin(all!(acceptor, log)), Box::pin(clients))).await;
+}
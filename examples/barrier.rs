@@ -0,0 +1,57 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep};
+
+pub struct Shard {
+    id: usize,
+    route: &'static str,
+}
+
+impl AsyncState<&'static str> for Shard {
+    async fn update(&mut self, route: &'static str) -> anyhow::Result<()> {
+        // shards take a different amount of time to switch over
+        sleep(Duration::from_millis(200 * self.id as u64)).await;
+        println!("shard {} now routes to {route}", self.id);
+        self.route = route;
+
+        Ok(())
+    }
+}
+
+impl Push<usize> for Shard {
+    async fn push(&mut self, item: usize) -> anyhow::Result<()> {
+        println!("shard {} sends {item} to {}", self.id, self.route);
+        Ok(())
+    }
+}
+
+pub struct Producer(StateBus<&'static str>);
+
+impl Poll for Producer {
+    type Item = usize;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        self.0.send_and_wait("new-routing").await?;
+        println!("all shards switched, producing");
+
+        let mut item = 0;
+
+        loop {
+            tx.send(item).await?;
+            item += 1;
+            sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut bus = StateBus::new();
+    let (a, b) = (bus.subscribe(), bus.subscribe());
+
+    let (producer, rx) = poll(Producer(bus));
+    let shard_a = push(rx.clone()).to(Shard { id: 1, route: "old-routing" }).with_state(a);
+    let shard_b = push(rx).to(Shard { id: 2, route: "old-routing" }).with_state(b);
+
+    let _ = all!(producer, shard_a, shard_b).await;
+}
@@ -0,0 +1,58 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep, timeout};
+
+pub struct Ticks;
+
+impl Poll for Ticks {
+    type Item = ();
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            tx.send(()).await?;
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// Counts what it has seen, across restarts
+#[derive(Default)]
+pub struct Tally(u64);
+
+impl Checkpoint for Tally {
+    type Snapshot = u64;
+
+    fn snapshot(&self) -> u64 {
+        self.0
+    }
+
+    fn restore(&mut self, snapshot: u64) {
+        self.0 = snapshot;
+    }
+}
+
+impl Push<()> for Tally {
+    async fn push(&mut self, _: ()) -> anyhow::Result<()> {
+        self.0 += 1;
+        println!("seen {}", self.0);
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // swap for `FileStore::new("state")` to keep the tally between runs of the example
+    let store = MemoryStore::new();
+
+    for run in 0..3 {
+        println!("run {run}");
+
+        let (ticks, rx) = poll(Ticks);
+        let tally = push(rx).to(Checkpointed::restore(Tally::default(), store.clone(), "tally").await?);
+
+        // dropping the pipeline saves the tally for the next run
+        let _ = timeout(Duration::from_millis(350), all!(ticks, tally)).await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval};
+
+pub struct Temperatures;
+
+impl Poll for Temperatures {
+    type Item = (String, f32);
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut timer = interval(Duration::from_millis(200));
+
+        for i in 0.. {
+            timer.tick().await;
+            tx.send((format!("sensor-{}", i % 3), 20.0 + i as f32 / 4.0)).await?;
+        }
+
+        unreachable!()
+    }
+}
+
+/// Stands in for a network hop, and garbles the 6th message
+pub struct Wire {
+    tx: channel::Sender<Bytes>,
+    sent: usize,
+}
+
+impl Push<Bytes> for Wire {
+    async fn push(&mut self, bytes: Bytes) -> anyhow::Result<()> {
+        println!("wire {}", String::from_utf8_lossy(&bytes));
+        self.sent += 1;
+
+        let bytes = if self.sent == 6 { bytes.slice(1..) } else { bytes };
+        self.tx.send(bytes).await?;
+
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (temperatures, rx) = poll(Temperatures);
+    let (encoder, rx) = encode(rx, Json);
+
+    let (tx, wire_rx) = channel::bounded(1);
+    let wire = push(rx).to(Wire { tx, sent: 0 });
+
+    let (decoder, rx) = decode::<(String, f32), _>(wire_rx, Json);
+    let printer = push(rx).to_fn(|(sensor, t)| println!("{sensor} is at {t}°C"));
+
+    // the garbled message stops the pipeline with the decode error
+    println!("{:?}", all!(temperatures, encoder, wire, decoder, printer).await);
+}
@@ -0,0 +1,44 @@
+use ppio::channel::bounded;
+use ppio::prelude::*;
+use tokio::time::{Duration, timeout};
+
+/// A legacy script, it chokes on a bad record and has to be started again
+const SCRIPT: &str = r#"
+echo "started" >&2
+while read -r line; do
+    [ "$line" = "bad" ] && { echo "cannot parse $line" >&2; exit 3; }
+    echo "$line" | tr a-z A-Z
+done
+"#;
+
+#[tokio::main]
+async fn main() {
+    let mut script = std::process::Command::new("sh");
+    script.args(["-c", SCRIPT]);
+
+    let (mut child, rx) = poll_command(script);
+    let child_stdin = child.stdin();
+    let child = child.restart(Restart::OnFailure).restart_delay(Duration::from_millis(100));
+
+    let (tx, records) = bounded(1);
+    let feeder = push(records).to(child_stdin);
+
+    let printer = push(rx).to_fn(|line: Line| match line {
+        Line::Stdout(line) => println!("out: {line}"),
+        Line::Stderr(line) => println!("err: {line}"),
+    });
+
+    let records = async move {
+        for record in ["alpha", "beta", "bad"] {
+            tx.send(record.to_string()).await.unwrap();
+        }
+
+        // anything sent while it dies goes down with it
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        tx.send("gamma".to_string()).await.unwrap();
+
+        std::future::pending::<()>().await
+    };
+
+    let _ = timeout(Duration::from_millis(1000), futures::future::join(records, all!(child, feeder, printer))).await;
+}
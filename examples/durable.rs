@@ -0,0 +1,50 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep};
+
+pub struct Orders(u64);
+
+impl Poll for Orders {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            tx.send(self.0).await?;
+            self.0 += 1;
+            sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+/// Goes down after a few orders, like a deploy in the middle of the stream
+pub struct Billing {
+    crash_at: Option<u64>,
+}
+
+impl Push<u64> for Billing {
+    async fn push(&mut self, order: u64) -> anyhow::Result<()> {
+        sleep(Duration::from_millis(50)).await;
+        anyhow::ensure!(Some(order) != self.crash_at, "crashed while billing order {order}");
+
+        println!("billed order {order}");
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join("ppio-durable");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // the first run dies halfway, with orders still queued in the log
+    let (orders, rx) = poll(Orders(0));
+    let billing = durable(rx, &dir).to(Billing { crash_at: Some(5) });
+
+    println!("{:?}", all!(orders, billing).await);
+
+    // the second run picks up from order 5 before taking new ones
+    let (orders, rx) = poll(Orders(100));
+    let billing = durable(rx, &dir).to(Billing { crash_at: None });
+
+    let _ = tokio::time::timeout(Duration::from_secs(1), all!(orders, billing)).await;
+}
@@ -0,0 +1,27 @@
+use ppio::prelude::*;
+
+/// Numbers every line, like `cat -n`
+pub struct Numbered {
+    n: usize,
+    out: StdoutPusher,
+}
+
+impl Push<String> for Numbered {
+    async fn push(&mut self, line: String) -> anyhow::Result<()> {
+        self.n += 1;
+        self.out.push(format!("{:6}\t{line}", self.n)).await
+    }
+}
+
+/// Try `seq 5 | cargo run --example filter --features stdio`
+#[tokio::main]
+async fn main() {
+    let (stdin, rx) = poll_stdin_lines();
+    let numbered = push(rx).to(Numbered { n: 0, out: WriterPusher::stdout() });
+
+    // stdin running out closes the pipeline once the last line is written
+    if let Err(ppio::Error::User(e)) = all!(stdin, numbered).await {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval};
+
+/// Deliberately not `Clone`, every subscriber sees the same allocation
+pub struct Frame {
+    id: usize,
+    pixels: Vec<u8>,
+}
+
+pub struct Camera;
+
+impl Poll for Camera {
+    type Item = Frame;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut id = 0;
+        let mut timer = interval(Duration::from_millis(500));
+
+        loop {
+            timer.tick().await;
+            tx.send(Frame { id, pixels: vec![id as u8; 1920 * 1080] }).await?;
+            id += 1;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (camera, [(display, dropped), (recorder, _)]) = poll(Camera).broadcast_shared(Channel::drop_oldest(4));
+
+    let display = push(display).to_fn(move |frame: Arc<Frame>| println!("showing frame {} ({} dropped)", frame.id, dropped.get()));
+    let recorder = push(recorder).to_fn(|frame: Arc<Frame>| println!("recording {} bytes", frame.pixels.len()));
+
+    let _ = all!(camera, display, recorder).await;
+}
@@ -0,0 +1,41 @@
+use std::fs;
+
+use ppio::prelude::*;
+use tokio::time::{Duration, sleep, timeout};
+
+/// Drops a few files into the folder the way an upload would
+async fn uploads(dir: &std::path::Path) -> std::io::Result<()> {
+    sleep(Duration::from_millis(100)).await;
+
+    // written in several pieces, shows up once as created
+    fs::create_dir_all(dir.join("2024/06"))?;
+    fs::write(dir.join("2024/06/orders.csv.part"), "id,total\n")?;
+    fs::write(dir.join("2024/06/orders.csv.part"), "id,total\n1,9.99\n")?;
+    fs::rename(dir.join("2024/06/orders.csv.part"), dir.join("2024/06/orders.csv"))?;
+
+    // not a csv, filtered out
+    fs::write(dir.join("notes.txt"), "skip me")?;
+
+    sleep(Duration::from_millis(300)).await;
+
+    fs::write(dir.join("2024/06/orders.csv"), "id,total\n1,9.99\n2,5.00\n")?;
+    sleep(Duration::from_millis(300)).await;
+
+    fs::remove_file(dir.join("2024/06/orders.csv"))?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join("ppio-fswatch");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+
+    let (watch, rx) = poll(FsWatch::new(&dir).filter("**/*.csv")?.coalesce(Duration::from_millis(50)));
+    let printer = push(rx).to_fn(|event: FsEvent| println!("{:?} {}", event.kind, event.path.display()));
+
+    let _ = timeout(Duration::from_millis(1500), futures::future::join(uploads(&dir), all!(watch, printer))).await;
+
+    Ok(())
+}
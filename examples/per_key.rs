@@ -0,0 +1,52 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep, timeout};
+
+pub struct Readings;
+
+impl Poll for Readings {
+    type Item = (&'static str, f32);
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        // the thermostat goes quiet after a while, and the fridge shows up late
+        for i in 0..30 {
+            tx.send(("boiler", 60.0 + i as f32)).await?;
+
+            if i < 10 {
+                tx.send(("thermostat", 20.0 + i as f32 / 10.0)).await?;
+            }
+
+            if i >= 12 {
+                tx.send(("fridge", 4.0)).await?;
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        std::future::pending().await
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (readings, rx) = poll(Readings);
+
+    // every device gets its own running average
+    let (devices, retired) = per_key(rx, |(device, _)| *device, Duration::from_millis(500), |device, rx| {
+        let device = *device;
+        println!("{device} showed up");
+
+        push(rx).to_state_fn((0.0, 0), move |(sum, n): &mut (f32, u32), (_, value)| {
+            *sum += value;
+            *n += 1;
+            println!("{device} averages {:.1}", *sum / *n as f32);
+        })
+    });
+
+    // with room for two, the fridge pushes out whichever device was quiet the longest
+    let devices = devices.max_keys(2);
+
+    let log = push(retired).to_fn(|r: Retired<&str>| println!("{} retired: {:?}", r.key, r.error));
+
+    let _ = timeout(Duration::from_secs(4), all!(readings, devices, log)).await;
+}
@@ -71,9 +71,9 @@ impl State<Parity> for Announcer {
 
 impl Push<usize> for Announcer {
     async fn push(&mut self, item: usize) -> anyhow::Result<()> {
-        if self.0.is_even() && item % 2 == 0 {
+        if self.0.is_even() && item.is_multiple_of(2) {
             println!("even: {}", item);
-        } else if !self.0.is_even() && item % 2 != 0 {
+        } else if !self.0.is_even() && !item.is_multiple_of(2) {
             println!("odd : {}", item);
         }
 
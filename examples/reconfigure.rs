@@ -0,0 +1,60 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval, sleep};
+
+pub struct Config;
+
+impl Poll for Config {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            for millis in [250, 0, 1000] {
+                tx.send(millis).await?;
+                sleep(Duration::from_secs(3)).await;
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Ticker(u64);
+
+impl AsyncState<u64> for Ticker {
+    async fn update(&mut self, millis: u64) -> anyhow::Result<()> {
+        anyhow::ensure!(millis > 0, "refusing to tick every 0ms");
+
+        // pretend to reconnect
+        sleep(Duration::from_millis(100)).await;
+        self.0 = millis;
+
+        Ok(())
+    }
+}
+
+impl Poll for Ticker {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        if self.0 == 0 {
+            futures::future::pending().await
+        }
+
+        let mut timer = interval(Duration::from_millis(self.0));
+
+        loop {
+            timer.tick().await;
+            tx.send(self.0).await?;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (config, rx) = poll(Config);
+    let (ticker, rx) = poll(Ticker::default()).with_state(rx);
+    let ticker = ticker.on_reject(Rejected::KeepPrevious);
+    let printer = push(rx).to_fn(|d| println!("tick every {d}ms"));
+
+    let _ = all!(config, ticker, printer).await;
+}
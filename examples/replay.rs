@@ -0,0 +1,34 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep, timeout};
+
+pub struct Readings(u32);
+
+impl Poll for Readings {
+    type Item = u32;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            tx.send(self.0).await?;
+            sleep(Duration::from_millis(100 + 50 * (self.0 as u64 % 3))).await;
+            self.0 += 1;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::temp_dir().join("ppio-readings.rec");
+
+    let (readings, rx) = poll(Readings(0));
+    let (recorder, rx) = record(rx, &path);
+    let live = push(rx).to_fn(|r| println!("live {r}"));
+
+    let _ = timeout(Duration::from_secs(1), all!(readings, recorder, live)).await;
+
+    // the same readings again, four times as fast
+    let (replay, rx) = poll_replay::<u32>(&path, Speed::Times(4.0));
+    let replayed = push(rx).to_fn(|r| println!("replayed {r}"));
+
+    println!("{:?}", all!(replay, replayed).await);
+}
@@ -0,0 +1,28 @@
+use ppio::prelude::*;
+use tokio::time::{Duration, sleep, timeout};
+
+/// A job that takes longer than the schedule allows
+pub struct Job(Missed);
+
+impl Push<Tick<Utc>> for Job {
+    async fn push(&mut self, tick: Tick<Utc>) -> anyhow::Result<()> {
+        println!("{:?}: {} missed {}", self.0, tick.at.format("%H:%M:%S"), tick.missed);
+        sleep(Duration::from_millis(1700)).await;
+
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    for policy in [Missed::Skip, Missed::CatchUp, Missed::Coalesce] {
+        // seconds first, every second, "0 2 * * MON-FRI" would be weekdays at 02:00
+        let (ticks, rx) = poll_schedule("* * * * * *", Utc)?;
+        let ticks = ticks.on_missed(policy);
+        let job = push(rx).to(Job(policy));
+
+        let _ = timeout(Duration::from_secs(10), all!(ticks, job)).await;
+    }
+
+    Ok(())
+}
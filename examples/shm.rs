@@ -0,0 +1,83 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, Instant, timeout};
+
+/// A fixed size record, safe to copy between processes as is
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Tick {
+    seq: u64,
+    price: f64,
+}
+
+unsafe impl Pod for Tick {}
+
+pub struct Feed;
+
+impl Poll for Feed {
+    type Item = Tick;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        for seq in 0.. {
+            tx.send(Tick { seq, price: 100.0 + (seq % 100) as f64 / 100.0 }).await?;
+        }
+
+        unreachable!()
+    }
+}
+
+pub struct Stats {
+    count: u64,
+    last: Option<u64>,
+    since: Instant,
+}
+
+impl Push<Tick> for Stats {
+    async fn push(&mut self, tick: Tick) -> anyhow::Result<()> {
+        // a restarted sender starts over at 0, otherwise nothing may go missing
+        if let Some(last) = self.last.filter(|_| tick.seq != 0) {
+            anyhow::ensure!(tick.seq == last + 1, "tick {tick:?} came after {last}");
+        }
+
+        self.last = Some(tick.seq);
+        self.count += 1;
+
+        if self.since.elapsed() >= Duration::from_millis(500) {
+            println!("{} ticks, last {tick:?}", self.count);
+            self.since = Instant::now();
+        }
+
+        Ok(())
+    }
+}
+
+/// Run with `send` and `recv` in two terminals, or with neither for both in one process
+#[tokio::main]
+async fn main() {
+    let path = "/dev/shm/ppio-ticks";
+    let role = std::env::args().nth(1).unwrap_or_default();
+
+    let send = async {
+        let (feed, rx) = poll(Feed).channel(Channel::bounded(1024));
+        let ring = push(rx).to(ShmPusher::create(path, 4096));
+
+        all!(feed, ring).await
+    };
+
+    let recv = async {
+        let (ring, rx) = poll(ShmPoller::<Tick>::open(path)).channel(Channel::bounded(1024));
+        let stats = push(rx).to(Stats { count: 0, last: None, since: Instant::now() });
+
+        all!(ring, stats).await
+    };
+
+    let run = async {
+        match role.as_str() {
+            "send" => send.await,
+            "recv" => recv.await,
+            _ => futures::future::select(Box::pin(send), Box::pin(recv)).await.factor_first().0,
+        }
+    };
+
+    println!("{:?}", timeout(Duration::from_secs(3), run).await);
+}
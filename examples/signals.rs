@@ -0,0 +1,65 @@
+use ppio::channel;
+use ppio::prelude::*;
+use tokio::time::{Duration, sleep};
+
+/// Counts up, by a step it rereads on SIGHUP
+#[derive(Default)]
+pub struct Counter {
+    n: u64,
+    step: u64,
+}
+
+impl AsyncState<Signal> for Counter {
+    async fn update(&mut self, signal: Signal) -> anyhow::Result<()> {
+        // stands in for reading the config file again
+        self.step *= 10;
+        println!("{signal}: counting by {} now", self.step);
+
+        Ok(())
+    }
+}
+
+impl Poll for Counter {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            self.n += self.step.max(1);
+            tx.send(self.n).await?;
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+fn kill(signal: &str) {
+    let pid = std::process::id().to_string();
+    std::process::Command::new("kill").args([signal, &pid]).status().unwrap();
+}
+
+/// Try it by hand with `kill -HUP` and `kill -TERM`, this sends them itself
+#[tokio::main]
+async fn main() {
+    let shutdown = Shutdown::new();
+
+    let (reload, rx) = poll_signals(&[Signal::Hup]);
+    let (counter, rx) = poll(Counter { n: 0, step: 1 }).with_state(rx);
+    let printer = push(rx).to_fn(|n| println!("{n}"));
+
+    let reload = shutdown.until(reload);
+    let counter = shutdown.until(counter);
+
+    tokio::spawn(async {
+        sleep(Duration::from_millis(350)).await;
+        kill("-HUP");
+        sleep(Duration::from_millis(350)).await;
+        kill("-TERM");
+    });
+
+    let on_signals = shutdown.on_signals();
+
+    // the sources are dropped on SIGTERM, the printer stops once it printed the rest
+    match shutdown.drain(all!(reload, counter, printer, on_signals)).await {
+        Err(ppio::Error::User(e)) => eprintln!("failed: {e:#}"),
+        _ => println!("stopped cleanly"),
+    }
+}
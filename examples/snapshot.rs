@@ -0,0 +1,96 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval, timeout};
+
+/// Emits 1, 2, 3... starting after `skip` numbers
+pub struct Numbers {
+    skip: u64,
+}
+
+impl Poll for Numbers {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut timer = interval(Duration::from_millis(30));
+
+        for n in self.skip + 1.. {
+            timer.tick().await;
+            tx.send(n).await?;
+        }
+
+        unreachable!()
+    }
+}
+
+/// Squares numbers on their way to the sum, a regular stage knowing nothing of snapshots
+pub struct Square(channel::Sender<u64>);
+
+impl Push<u64> for Square {
+    async fn push(&mut self, n: u64) -> anyhow::Result<()> {
+        self.0.send(n * n).await?;
+        Ok(())
+    }
+}
+
+/// Sums both sources, the sum is the same whether or not the pipeline restarted
+#[derive(Default)]
+pub struct Sum(u64);
+
+impl Checkpoint for Sum {
+    type Snapshot = u64;
+
+    fn snapshot(&self) -> u64 {
+        self.0
+    }
+
+    fn restore(&mut self, snapshot: u64) {
+        println!("restored sum {snapshot}");
+        self.0 = snapshot;
+    }
+}
+
+impl Push<u64> for Sum {
+    async fn push(&mut self, n: u64) -> anyhow::Result<()> {
+        self.0 += n;
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let store = MemoryStore::new();
+
+    for run in 0..3 {
+        let barriers = Barriers::new(store.clone()).await?;
+        let (l, r) = (barriers.offset("left").await?, barriers.offset("right").await?);
+
+        // the restored sum always matches the restored source offsets
+        println!("run {run}, epoch {:?}, sources at {l} and {r}, expecting sum {}", barriers.restored(), l * (l + 1) / 2 + r * (r + 1) * (2 * r + 1) / 6);
+
+        // each source picks up right after the last item the snapshot covers
+        let (left, rx) = poll(Numbers { skip: l });
+        let (left_barriers, left_rx) = barriers.source(rx, "left").await?;
+
+        let (right, rx) = poll(Numbers { skip: r });
+        let (right_barriers, right_rx) = barriers.source(rx, "right").await?;
+
+        // the right side is squared before the sum, its barriers pass along with the squares
+        let (square, right_rx) = barriers.through(right_rx, Square);
+
+        let sum = barriers.stage([left_rx, right_rx], Sum::default(), "sum").await?;
+
+        let trigger = async {
+            let mut timer = interval(Duration::from_millis(100));
+
+            loop {
+                timer.tick().await;
+                barriers.trigger();
+            }
+        };
+
+        let pipeline = all!(left, left_barriers, right, right_barriers, square, sum);
+        let _ = timeout(Duration::from_millis(450), futures::future::join(pipeline, trigger)).await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep};
+
+/// Produces bursts of 500 items every 100ms
+pub struct Burst;
+
+impl Poll for Burst {
+    type Item = u64;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut next = 0;
+
+        loop {
+            for _ in 0..500 {
+                tx.send(next).await?;
+                next += 1;
+            }
+
+            sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// Takes a millisecond per item, so it falls behind during bursts
+#[derive(Default)]
+pub struct Slow(u64);
+
+impl Push<u64> for Slow {
+    async fn push(&mut self, item: u64) -> anyhow::Result<()> {
+        anyhow::ensure!(item == self.0, "expected {} but got {item}", self.0);
+        self.0 += 1;
+
+        if item.is_multiple_of(500) {
+            println!("at {item}");
+        }
+
+        sleep(Duration::from_millis(1)).await;
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (burst, rx) = poll(Burst);
+
+    // anything past 64 waiting items goes to disk until the consumer catches up
+    let (spill, rx) = spill(rx, std::env::temp_dir().join("ppio-spill"));
+    let spill = spill.memory(64).segment(256);
+
+    let slow = push(rx).to(Slow::default());
+
+    let _ = all!(burst, spill, slow).await;
+}
@@ -0,0 +1,59 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval};
+
+#[derive(Default)]
+pub struct Counter {
+    step: usize,
+}
+
+impl State<usize> for Counter {
+    fn update(&mut self, step: usize) {
+        self.step = step;
+    }
+}
+
+impl Poll for Counter {
+    type Item = usize;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut count = 0;
+        let mut timer = interval(Duration::from_millis(500));
+
+        loop {
+            timer.tick().await;
+            tx.send(count).await?;
+            count += self.step;
+        }
+    }
+}
+
+pub struct Settings;
+
+impl Poll for Settings {
+    type Item = usize;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut step = 1;
+        let mut timer = interval(Duration::from_secs(3));
+
+        loop {
+            timer.tick().await;
+            tx.send(step).await?;
+            step += 1;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (settings, [step, label]) = poll(Settings).broadcast();
+    let (counter, [evens, steps]) = poll(Counter::default()).broadcast().with_state(step);
+
+    let evens = push(evens).to_fn(|n: usize| if n.is_multiple_of(2) { println!("even: {n}") });
+    let labeled = push(steps)
+        .to_state_fn(0, |step: &mut usize, n| println!("step {step}: {n}"))
+        .with_state(label);
+
+    let _ = all!(settings, counter, evens, labeled).await;
+}
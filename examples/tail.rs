@@ -0,0 +1,46 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use ppio::prelude::*;
+use tokio::time::{Duration, sleep, timeout};
+
+/// Plays the part of an app writing its log, rotating it halfway through
+async fn app(dir: &std::path::Path) -> std::io::Result<()> {
+    let log = dir.join("app.log");
+
+    for i in 0..20 {
+        if i == 10 {
+            fs::rename(&log, dir.join("app.log.1"))?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&log)?;
+        writeln!(file, "request {i} handled")?;
+
+        sleep(Duration::from_millis(50)).await;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = std::env::temp_dir().join("ppio-tail");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let tail = || FileTail::lines(dir.join("app.log")).offset_file(dir.join("app.log.offset")).interval(Duration::from_millis(20));
+
+    // follows the log across the rotation
+    let (follow, rx) = poll(tail());
+    let printer = push(rx).to_fn(|line: String| println!("{line}"));
+
+    let _ = timeout(Duration::from_millis(1500), futures::future::join(app(&dir), all!(follow, printer))).await;
+
+    // a restart only sees what was written while it was down
+    OpenOptions::new().append(true).open(dir.join("app.log")).unwrap().write_all(b"written while down\n").unwrap();
+
+    let (follow, rx) = poll(tail());
+    let printer = push(rx).to_fn(|line: String| println!("after restart: {line}"));
+
+    let _ = timeout(Duration::from_millis(200), all!(follow, printer)).await;
+}
@@ -0,0 +1,54 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval, sleep};
+
+#[derive(Default)]
+pub struct Counter;
+
+impl Poll for Counter {
+    type Item = usize;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut count = 0;
+        let mut timer = interval(Duration::from_millis(250));
+
+        loop {
+            timer.tick().await;
+            tx.send(count).await?;
+            count += 1;
+        }
+    }
+}
+
+pub struct Tapper(Subscriptions<usize>);
+
+impl Poll for Tapper {
+    type Item = String;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut tap = 0;
+
+        loop {
+            sleep(Duration::from_secs(2)).await;
+
+            // every tap starts with a bit of history, and detaches after a few items
+            let (rx, _) = self.0.subscribe_with_last(3);
+            for _ in 0..6 {
+                tx.send(format!("tap {tap}: {}", rx.recv().await?)).await?;
+            }
+
+            tap += 1;
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let (counter, subscriptions) = poll(Counter).broadcast_dynamic(Channel::bounded(16));
+    let counter = counter.keep_last(3);
+
+    let (tapper, rx) = poll(Tapper(subscriptions));
+    let printer = push(rx).to_fn(|line| println!("{line}"));
+
+    let _ = all!(counter, tapper, printer).await;
+}
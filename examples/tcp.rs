@@ -0,0 +1,51 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, sleep};
+
+pub struct Jobs;
+
+impl Poll for Jobs {
+    type Item = u32;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        for job in 0.. {
+            tx.send(job).await?;
+        }
+
+        unreachable!()
+    }
+}
+
+/// Logs when a job made it onto the link
+pub struct Sent(TcpPusher<u32, Bincode>);
+
+impl Push<u32> for Sent {
+    async fn push(&mut self, job: u32) -> anyhow::Result<()> {
+        self.0.push(job).await?;
+        println!("sent job {job}");
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // usually these two halves live in different processes
+    let (jobs, rx) = poll(Jobs).channel(Channel::bounded(1));
+    let sender = push(rx).to(Sent(TcpPusher::connect("127.0.0.1:7878", Bincode)));
+
+    // credit only goes back once the worker took a job, so at most 4 are in flight
+    let (listener, rx) = poll(TcpPoller::bind("127.0.0.1:7878", Bincode).window(4)).channel(Channel::bounded(1));
+    let worker = push(rx).to(Worker);
+
+    let _ = tokio::time::timeout(Duration::from_secs(2), all!(jobs, sender, listener, worker)).await;
+}
+
+pub struct Worker;
+
+impl Push<u32> for Worker {
+    async fn push(&mut self, job: u32) -> anyhow::Result<()> {
+        sleep(Duration::from_millis(200)).await;
+        println!("done with job {job}");
+        Ok(())
+    }
+}
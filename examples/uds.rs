@@ -0,0 +1,37 @@
+use ppio::prelude::*;
+use ppio::channel;
+use tokio::time::{Duration, interval, timeout};
+
+pub struct Metrics;
+
+impl Poll for Metrics {
+    type Item = (String, u64);
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut timer = interval(Duration::from_millis(250));
+
+        for n in 0.. {
+            timer.tick().await;
+            tx.send(("requests".to_owned(), n * 7)).await?;
+        }
+
+        unreachable!()
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::var("METRICS_SOCKET").unwrap_or_else(|_| "/tmp/ppio-metrics.sock".to_owned());
+
+    // a socket file left behind by a crashed run, the poller replaces it
+    drop(std::os::unix::net::UnixListener::bind(&path));
+
+    let (metrics, rx) = poll(Metrics);
+    let exporter = push(rx).to(UdsPusher::connect(&path, Bincode));
+
+    let (collector, rx) = poll(UdsPoller::bind(&path, Bincode));
+    let printer = push(rx).to_fn(|(name, value): (String, u64)| println!("{name} = {value}"));
+
+    let _ = timeout(Duration::from_secs(2), all!(metrics, exporter, collector, printer)).await;
+    println!("socket cleaned up: {}", !std::path::Path::new(&path).exists());
+}
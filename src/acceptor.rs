@@ -0,0 +1,150 @@
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::{select, Either};
+use futures::FutureExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+
+use crate::channel;
+use crate::io::Poll;
+use crate::Error::*;
+
+const MIN_PAUSE: Duration = Duration::from_millis(10);
+const MAX_PAUSE: Duration = Duration::from_secs(1);
+
+/// A connection's pipeline stopped, made by [`TcpAcceptor`]
+#[derive(Debug)]
+pub struct Closed {
+    pub peer: SocketAddr,
+    pub error: crate::Error,
+}
+
+/// Runs a pipeline for every connection it accepts
+///
+/// `template` gets each new stream and builds its stages, usually joined with `all!`.
+/// Every pipeline runs as its own task, one stopping (the client went away, a stage
+/// failed or panicked) leaves the others alone and is reported as a [`Closed`] item,
+/// as long as anyone still takes them.
+/// Stopping the acceptor tears down every pipeline it started.
+///
+/// Accept errors that pass, like a client hanging up before it was accepted or running
+/// out of file descriptors, are logged and accepting pauses for a moment, doubling up
+/// to a second while they last. Anything else fails the acceptor.
+pub struct TcpAcceptor<F> {
+    addr: String,
+    template: F,
+    max: usize,
+}
+
+impl<F, P> TcpAcceptor<F>
+where
+    F: Fn(TcpStream, SocketAddr) -> P,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>>,
+{
+    /// Listens on `addr` once polled
+    pub fn bind(addr: impl Into<String>, template: F) -> Self {
+        Self {
+            addr: addr.into(),
+            template,
+            max: usize::MAX,
+        }
+    }
+
+    /// Stops accepting while `connections` pipelines are running, unlimited by default
+    pub fn max_connections(mut self, connections: usize) -> Self {
+        self.max = connections.max(1);
+        self
+    }
+}
+
+impl<F, P> Poll for TcpAcceptor<F>
+where
+    F: Fn(TcpStream, SocketAddr) -> P + Send + Sync,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>> + 'static,
+    P::IntoFuture: Send + 'static,
+{
+    type Item = Closed;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        let mut pipelines = JoinSet::new();
+        let mut paused: Option<(Instant, Duration)> = None;
+
+        loop {
+            let until = paused.map(|(until, _)| until);
+            let accept = async {
+                if let Some(until) = until {
+                    sleep_until(until).await;
+                }
+
+                listener.accept().await
+            };
+
+            let event = if pipelines.is_empty() {
+                Either::Left(accept.await)
+            } else if pipelines.len() >= self.max {
+                Either::Right(pipelines.join_next().await.unwrap())
+            } else {
+                match select(Box::pin(accept), Box::pin(pipelines.join_next())).await {
+                    Either::Left((accepted, _)) => Either::Left(accepted),
+                    Either::Right((joined, _)) => Either::Right(joined.unwrap()),
+                }
+            };
+
+            let (stream, peer) = match event {
+                Either::Left(Ok(accepted)) => {
+                    paused = None;
+                    accepted
+                },
+                Either::Left(Err(e)) if is_transient(&e) => {
+                    let delay = paused.map_or(MIN_PAUSE, |(_, delay)| (delay * 2).min(MAX_PAUSE));
+
+                    log::warn!("accepting on {}: {e}, trying again in {delay:?}", self.addr);
+                    paused = Some((Instant::now() + delay, delay));
+                    continue;
+                },
+                Either::Left(Err(e)) => return Err(e.into()),
+                Either::Right(joined) => {
+                    let closed = joined.map_err(|e| anyhow!("connection task failed: {e}"))?;
+                    // nobody has to watch connections close, the listener keeps going without them
+                    let _ = tx.send(closed).await;
+                    continue;
+                },
+            };
+
+            let pipeline = (self.template)(stream, peer).into_future();
+
+            pipelines.spawn(async move {
+                let error = match AssertUnwindSafe(pipeline).catch_unwind().await {
+                    Ok(Err(error)) => error,
+                    Ok(Ok(never)) => match never {},
+                    Err(_) => Internal(anyhow!("pipeline panicked")),
+                };
+
+                Closed { peer, error }
+            });
+        }
+    }
+}
+
+/// Whether accepting can go on after `e`, fatal errors are those of a broken listener
+fn is_transient(e: &io::Error) -> bool {
+    match e.kind() {
+        ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset | ErrorKind::Interrupted | ErrorKind::TimedOut => true,
+        ErrorKind::WouldBlock | ErrorKind::OutOfMemory => true,
+        #[cfg(unix)]
+        _ => matches!(
+            e.raw_os_error(),
+            Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM | libc::EPROTO | libc::EPERM)
+        ),
+        #[cfg(not(unix))]
+        _ => false,
+    }
+}
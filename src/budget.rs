@@ -0,0 +1,411 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::Duration;
+use std::{task, task::Poll::*};
+
+use futures::future::BoxFuture;
+use tokio::time::Sleep;
+
+use crate::channel::{Sender, WeakReceiver};
+
+/// How often a meter looks whether its channel was emptied, while others wait on the budget
+const RECHECK: Duration = Duration::from_millis(5);
+
+/// How many bytes an item holds on to while it waits in a queue
+pub trait ByteSize {
+    fn byte_size(&self) -> usize;
+}
+
+impl ByteSize for [u8] {
+    fn byte_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ByteSize for str {
+    fn byte_size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl ByteSize for Vec<u8> {
+    fn byte_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl ByteSize for String {
+    fn byte_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: ByteSize + ?Sized> ByteSize for Box<T> {
+    fn byte_size(&self) -> usize {
+        (**self).byte_size()
+    }
+}
+
+impl<T: ByteSize + ?Sized> ByteSize for Arc<T> {
+    fn byte_size(&self) -> usize {
+        (**self).byte_size()
+    }
+}
+
+struct Inner {
+    limit: usize,
+    used: AtomicUsize,
+    dropped: AtomicU64,
+    waiters: Mutex<Vec<Waker>>,
+    /// meters with items in their channel, they only find out those were received when polled
+    holders: Mutex<Vec<Waker>>,
+}
+
+/// A number of bytes that queued items may hold, shared by every stage it is given to
+///
+/// Clone the same budget into all stages of a pipeline to bound the pipeline as a whole.
+/// Items hold on to their bytes until the next stage received them. A single item larger
+/// than the budget still goes through once nothing else is queued.
+#[derive(Clone)]
+pub struct Budget(Arc<Inner>);
+
+impl Budget {
+    pub fn new(limit: usize) -> Self {
+        Self(Arc::new(Inner {
+            limit,
+            used: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+            waiters: Mutex::new(Vec::new()),
+            holders: Mutex::new(Vec::new()),
+        }))
+    }
+
+    pub fn limit(&self) -> usize {
+        self.0.limit
+    }
+
+    /// Bytes currently held by queued items
+    ///
+    /// An item that was received counts until its stage runs again or someone waits on the budget.
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Acquire)
+    }
+
+    /// Items dropped by stages over the budget, with [`OverBudget::DropNewest`] or [`OverBudget::DropOldest`]
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+
+    fn count_drop(&self) {
+        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn try_acquire(&self, bytes: usize) -> bool {
+        self.0
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                (used == 0 || used + bytes <= self.0.limit).then_some(used + bytes)
+            })
+            .is_ok()
+    }
+
+    fn poll_acquire(&self, bytes: usize, cx: &mut task::Context<'_>) -> task::Poll<()> {
+        if self.try_acquire(bytes) {
+            return Ready(());
+        }
+
+        {
+            let mut waiters = self.0.waiters.lock().unwrap();
+            if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+                waiters.push(cx.waker().clone());
+            }
+        }
+
+        // bytes of items that were received already are only released once their meter looks
+        let holders = std::mem::take(&mut *self.0.holders.lock().unwrap());
+        holders.into_iter().filter(|waker| !waker.will_wake(cx.waker())).for_each(Waker::wake);
+
+        // bytes may have been released before the waker was registered
+        if self.try_acquire(bytes) {
+            Ready(())
+        } else {
+            Pending
+        }
+    }
+
+    fn is_contended(&self) -> bool {
+        !self.0.waiters.lock().unwrap().is_empty()
+    }
+
+    fn hold(&self, cx: &mut task::Context<'_>) {
+        let mut holders = self.0.holders.lock().unwrap();
+        if !holders.iter().any(|waker| waker.will_wake(cx.waker())) {
+            holders.push(cx.waker().clone());
+        }
+    }
+
+    fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+
+        self.0.used.fetch_sub(bytes, Ordering::AcqRel);
+
+        let waiters = std::mem::take(&mut *self.0.waiters.lock().unwrap());
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// What a budgeted stage does with an item that doesn't fit in its [`Budget`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverBudget {
+    /// The producer waits until enough bytes are released
+    #[default]
+    Wait,
+    /// The new item is dropped
+    DropNewest,
+    /// The stage's oldest items are dropped to make room, including one waiting in its
+    /// channel. When it has none left the budget is held by other stages, and the new
+    /// item is dropped instead.
+    DropOldest,
+}
+
+type SizeFn<T> = Arc<dyn Fn(&T) -> usize + Send + Sync>;
+
+/// A [`Budget`] along with how to apply it, remembered by a stage until its queues are made
+pub(crate) struct Metering<T> {
+    budget: Budget,
+    policy: OverBudget,
+    size: SizeFn<T>,
+}
+
+impl<T> Clone for Metering<T> {
+    fn clone(&self) -> Self {
+        Self {
+            budget: self.budget.clone(),
+            policy: self.policy,
+            size: self.size.clone(),
+        }
+    }
+}
+
+impl<T: ByteSize> Metering<T> {
+    pub fn new(budget: Budget, policy: OverBudget) -> Self {
+        Self {
+            budget,
+            policy,
+            size: Arc::new(|item: &T| item.byte_size()),
+        }
+    }
+}
+
+impl<T: 'static> Metering<T> {
+    /// The same metering, for items shared through an [`Arc`]
+    pub fn shared(self) -> Metering<Arc<T>> {
+        let size = self.size;
+
+        Metering {
+            budget: self.budget,
+            policy: self.policy,
+            size: Arc::new(move |item: &Arc<T>| size(item)),
+        }
+    }
+}
+
+/// A queue in front of a channel, holding on to bytes from a [`Budget`] while items wait in
+/// it, and in the channel until they're received
+///
+/// The meter has to be the only one sending on the channel, it tells received items by
+/// how many are left in there.
+pub(crate) struct Meter<T> {
+    metering: Metering<T>,
+    /// to see which items were received, and for [`OverBudget::DropOldest`] to take one back out
+    recver: WeakReceiver<T>,
+    queue: VecDeque<(T, usize)>,
+    /// an item waiting for the budget, with [`OverBudget::Wait`]
+    held: Option<(T, usize)>,
+    sending: Option<(BoxFuture<'static, bool>, usize)>,
+    /// sizes of the items in the channel, oldest first
+    delivered: VecDeque<usize>,
+    recheck: Option<Pin<Box<Sleep>>>,
+}
+
+impl<T: Send + 'static> Meter<T> {
+    pub fn new(metering: Metering<T>, recver: WeakReceiver<T>) -> Self {
+        Self {
+            metering,
+            recver,
+            queue: VecDeque::new(),
+            held: None,
+            sending: None,
+            delivered: VecDeque::new(),
+            recheck: None,
+        }
+    }
+
+    /// Queues `item` according to the policy, returns how many items were dropped for it
+    pub fn offer(&mut self, item: T) -> usize {
+        let size = (self.metering.size)(&item);
+        let budget = self.metering.budget.clone();
+        let mut dropped = 0;
+
+        self.reconcile();
+
+        match self.metering.policy {
+            OverBudget::Wait => {
+                if budget.try_acquire(size) {
+                    self.queue.push_back((item, size));
+                } else {
+                    self.held = Some((item, size));
+                }
+            },
+            OverBudget::DropNewest => {
+                if budget.try_acquire(size) {
+                    self.queue.push_back((item, size));
+                } else {
+                    dropped += 1;
+                }
+            },
+            OverBudget::DropOldest => loop {
+                if budget.try_acquire(size) {
+                    self.queue.push_back((item, size));
+                    break;
+                }
+
+                match self.evict_oldest() {
+                    Some(evicted) => dropped += evicted as usize,
+                    // nothing of its own left, the budget is held by other stages
+                    None => {
+                        dropped += 1;
+                        break;
+                    },
+                }
+            },
+        }
+
+        (0..dropped).for_each(|_| budget.count_drop());
+
+        dropped
+    }
+
+    /// Drops the oldest item this meter holds bytes for, `None` when it holds none
+    ///
+    /// `Some(false)` when it turned out to be received already, that frees its bytes all the same.
+    fn evict_oldest(&mut self) -> Option<bool> {
+        let budget = &self.metering.budget;
+
+        if let Some(oldest) = self.delivered.pop_front() {
+            let evicted = self.recver.upgrade().is_some_and(|rx| rx.try_recv().is_ok());
+            budget.release(oldest);
+
+            return Some(evicted);
+        }
+
+        // a send that is still pending never got its item into the channel
+        if let Some((_, oldest)) = self.sending.take() {
+            budget.release(oldest);
+            return Some(true);
+        }
+
+        let (_, oldest) = self.queue.pop_front()?;
+        budget.release(oldest);
+
+        Some(true)
+    }
+
+    /// Releases the bytes of the items that left the channel
+    fn reconcile(&mut self) {
+        let left = self.recver.upgrade().map_or(0, |rx| rx.len());
+
+        while self.delivered.len() > left {
+            self.metering.budget.release(self.delivered.pop_front().unwrap());
+        }
+    }
+
+    /// Hands queued items to `sender`, ready once the meter can take another item
+    pub fn poll_flush(&mut self, sender: &Sender<T>, cx: &mut task::Context<'_>) -> task::Poll<()> {
+        loop {
+            self.reconcile();
+
+            if let Some((item, size)) = self.held.take() {
+                match self.metering.budget.poll_acquire(size, cx) {
+                    Ready(()) => self.queue.push_back((item, size)),
+                    Pending => self.held = Some((item, size)),
+                }
+            }
+
+            if let Some((fut, size)) = self.sending.as_mut() {
+                let Ready(sent) = fut.as_mut().poll(cx) else {
+                    break;
+                };
+
+                // the bytes are held until the item is received, unless the receiver is gone
+                if sent {
+                    self.delivered.push_back(*size);
+                } else {
+                    self.metering.budget.release(*size);
+                }
+
+                self.sending = None;
+                continue;
+            }
+
+            let Some((item, size)) = self.queue.pop_front() else {
+                break;
+            };
+
+            let sender = sender.clone();
+            self.sending = Some((
+                Box::pin(async move { sender.send(item).await.is_ok() }),
+                size,
+            ));
+        }
+
+        self.poll_recheck(cx);
+
+        if self.held.is_some() {
+            Pending
+        } else {
+            Ready(())
+        }
+    }
+}
+
+impl<T> Meter<T> {
+    /// Nothing wakes the meter when its channel is emptied, so while others wait on the
+    /// budget it looks every so often
+    fn poll_recheck(&mut self, cx: &mut task::Context<'_>) {
+        if self.delivered.is_empty() {
+            self.recheck = None;
+            return;
+        }
+
+        let budget = &self.metering.budget;
+        budget.hold(cx);
+
+        if !budget.is_contended() {
+            return;
+        }
+
+        let recheck = self.recheck.get_or_insert_with(|| Box::pin(tokio::time::sleep(RECHECK)));
+
+        if recheck.as_mut().poll(cx).is_ready() {
+            self.recheck = None;
+            cx.waker().wake_by_ref();
+        }
+    }
+}
+
+impl<T> Drop for Meter<T> {
+    fn drop(&mut self) {
+        let queued: usize = self.queue.iter().map(|(_, size)| size).sum();
+        let sending = self.sending.as_ref().map_or(0, |(_, size)| *size);
+        let delivered: usize = self.delivered.iter().sum();
+
+        self.metering.budget.release(queued + sending + delivered);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{pin::Pin, task, task::Poll::*};
+
+use futures::Stream;
+
+use crate::budget::{Meter, Metering};
+
+pub use async_channel::{
+    bounded, unbounded, Receiver, Recv, RecvError, Send, SendError, Sender, TryRecvError,
+    TrySendError, WeakReceiver,
+};
+
+/// How a stage's output channel behaves once its consumer falls behind
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Channel {
+    /// Never full, memory grows with the backlog
+    #[default]
+    Unbounded,
+    /// The producer waits for room
+    Bounded(usize),
+    /// A full channel drops the new item
+    DropNewest(usize),
+    /// A full channel drops its oldest item to make room
+    DropOldest(usize),
+    /// Only the most recent item is kept
+    Latest,
+}
+
+impl Channel {
+    pub fn unbounded() -> Self {
+        Self::Unbounded
+    }
+
+    pub fn bounded(capacity: usize) -> Self {
+        Self::Bounded(capacity)
+    }
+
+    pub fn drop_newest(capacity: usize) -> Self {
+        Self::DropNewest(capacity)
+    }
+
+    pub fn drop_oldest(capacity: usize) -> Self {
+        Self::DropOldest(capacity)
+    }
+
+    pub fn latest() -> Self {
+        Self::Latest
+    }
+
+    /// The channel for this policy, a capacity of 0 is made 1 here and refused by
+    /// [`Channel::validate`] when a stage using it starts
+    pub fn create<T>(self) -> (Sender<T>, Receiver<T>) {
+        match self {
+            Self::Unbounded => unbounded(),
+            Self::Bounded(capacity) | Self::DropNewest(capacity) | Self::DropOldest(capacity) => bounded(capacity.max(1)),
+            Self::Latest => bounded(1),
+        }
+    }
+
+    /// Fails for a capacity of 0, a channel can't hand over an item without holding it
+    pub fn validate(self) -> anyhow::Result<Self> {
+        match self {
+            Self::Bounded(0) | Self::DropNewest(0) | Self::DropOldest(0) => {
+                anyhow::bail!("{self:?} has no room for an item, the capacity must be at least 1")
+            },
+            _ => Ok(self),
+        }
+    }
+
+    /// Whether sending can lose items instead of waiting
+    pub fn is_lossy(self) -> bool {
+        matches!(self, Self::DropNewest(_) | Self::DropOldest(_) | Self::Latest)
+    }
+}
+
+/// The output side of a stage, relaying through a [`Channel`] policy when it is lossy,
+/// or through a [`Meter`] when it has a budget
+pub(crate) struct Outlet<T> {
+    channel: Channel,
+    sender: Sender<T>,
+    /// kept for the drop oldest policies, to make room in a full channel
+    recver: Option<Receiver<T>>,
+    relay_sender: Option<Sender<T>>,
+    relay: Option<Pin<Box<Receiver<T>>>>,
+    meter: Option<Meter<T>>,
+    metering: Option<Metering<T>>,
+}
+
+impl<T> Outlet<T> {
+    pub fn new(channel: Channel) -> (Self, Receiver<T>) {
+        let (tx, rx) = channel.create();
+        let keep = matches!(channel, Channel::DropOldest(_) | Channel::Latest);
+
+        let outlet = Self {
+            channel,
+            sender: tx,
+            recver: keep.then(|| rx.clone()),
+            relay_sender: None,
+            relay: None,
+            meter: None,
+            metering: None,
+        };
+
+        (outlet, rx)
+    }
+
+    /// Fails when the stage was given an invalid [`Channel`]
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.channel.validate().map(drop)
+    }
+
+    /// The budget this stage was given, for stages built from it to apply as well
+    pub fn metering(&self) -> Option<Metering<T>> {
+        self.metering.clone()
+    }
+
+    /// The sender handed to [`Poll::poll`](crate::io::Poll::poll)
+    pub fn sender(&mut self) -> Sender<T> {
+        if !self.channel.is_lossy() && self.meter.is_none() {
+            return self.sender.clone();
+        }
+
+        let relay = &mut self.relay;
+        let tx = self.relay_sender.get_or_insert_with(|| {
+            let (tx, rx) = bounded(1);
+            *relay = Some(Box::pin(rx));
+            tx
+        });
+
+        tx.clone()
+    }
+}
+
+impl<T: std::marker::Send + 'static> Outlet<T> {
+    pub fn metered(metering: Metering<T>) -> (Self, Receiver<T>) {
+        let (mut outlet, rx) = Self::new(Channel::bounded(1));
+
+        outlet.meter = Some(Meter::new(metering.clone(), rx.downgrade()));
+        outlet.metering = Some(metering);
+
+        (outlet, rx)
+    }
+
+    /// Moves relayed items into the output channel, never finishes
+    pub fn poll_relay(&mut self, cx: &mut task::Context<'_>) -> task::Poll<()> {
+        let Some(relay) = self.relay.as_mut() else {
+            return Pending;
+        };
+
+        // the consumer is gone, fail the producer's sends like a plain channel would
+        if self.sender.receiver_count() == self.recver.is_some() as usize {
+            relay.close();
+            return Pending;
+        }
+
+        if let Some(meter) = self.meter.as_mut() {
+            // items are left in the relay while waiting for the budget, blocking the producer
+            while meter.poll_flush(&self.sender, cx).is_ready() {
+                match relay.as_mut().poll_next(cx) {
+                    Ready(Some(item)) => {
+                        meter.offer(item);
+                    },
+                    _ => break,
+                }
+            }
+
+            return Pending;
+        }
+
+        while let Ready(Some(item)) = relay.as_mut().poll_next(cx) {
+            let Err(TrySendError::Full(item)) = self.sender.try_send(item) else {
+                continue;
+            };
+
+            if let Some(recver) = &self.recver {
+                let _ = recver.try_recv();
+                let _ = self.sender.try_send(item);
+            }
+        }
+
+        Pending
+    }
+}
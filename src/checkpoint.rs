@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{self, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::{ready, BoxFuture};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+
+use crate::channel;
+use crate::io::{AsyncState, Poll, Push, State};
+
+/// Stage state that can be saved and brought back after a restart
+pub trait Checkpoint {
+    type Snapshot: Serialize + DeserializeOwned;
+
+    fn snapshot(&self) -> Self::Snapshot;
+    fn restore(&mut self, snapshot: Self::Snapshot);
+}
+
+/// Where [`Checkpointed`] stages keep their snapshots
+pub trait Store: Send + Sync {
+    fn save<'a>(&'a self, key: &'a str, bytes: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>>;
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>>;
+
+    fn remove<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(ready(Ok(())))
+    }
+}
+
+/// One file per key in a local directory, replaced atomically on every save
+///
+/// Keys are used as file names, so they can't have path separators in them.
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Keeps the files in `dir`, it's created on the first save
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str, ext: &str) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(!key.contains(|c| path::is_separator(c) || c == '\0'), "checkpoint key {key:?} isn't a file name");
+        Ok(self.dir.join(format!("{key}.{ext}")))
+    }
+}
+
+impl Store for FileStore {
+    fn save<'a>(&'a self, key: &'a str, bytes: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let tmp = self.path(key, "tmp")?;
+
+            fs::create_dir_all(&self.dir).await?;
+            fs::write(&tmp, bytes).await?;
+            fs::rename(tmp, self.path(key, "checkpoint")?).await?;
+
+            Ok(())
+        })
+    }
+
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match fs::read(self.path(key, "checkpoint")?).await {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            match fs::remove_file(self.path(key, "checkpoint")?).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+/// Keeps snapshots in memory, clones share the same snapshots
+#[derive(Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<HashMap<String, Vec<u8>>>>);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn save<'a>(&'a self, key: &'a str, bytes: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.0.lock().unwrap().insert(key.to_owned(), bytes.to_vec());
+        Box::pin(ready(Ok(())))
+    }
+
+    fn load<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(ready(Ok(self.0.lock().unwrap().get(key).cloned())))
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.0.lock().unwrap().remove(key);
+        Box::pin(ready(Ok(())))
+    }
+}
+
+/// Wraps a poller or pusher so its [`Checkpoint`] is saved under `key` as it runs
+///
+/// The last snapshot is restored when the wrapper is created, so before the first
+/// `poll()`/`push()`. After that pushers save at most once per [`Checkpointed::every`],
+/// a stateful stage saves after every state update, and every stage saves once more
+/// when its driver is dropped. A failed save fails the stage, except for the one on drop,
+/// which is logged.
+///
+/// A snapshot needs the stage to hold still, so a poller isn't saved while its `poll()`
+/// runs, only around state updates, when it ends and on drop.
+///
+/// The inner stage can be [`State`] or [`AsyncState`]. Its updates are run, and saved,
+/// right before the next `poll()`/`push()`, and one that fails fails the stage.
+pub struct Checkpointed<C: Checkpoint> {
+    inner: C,
+    store: Arc<dyn Store>,
+    key: String,
+    every: Duration,
+    saved: Instant,
+    /// state updates still to run on `inner`
+    updates: Vec<Update<C>>,
+}
+
+type Update<C> = Box<dyn for<'a> FnOnce(&'a mut C) -> BoxFuture<'a, anyhow::Result<()>> + Send>;
+
+impl<C: Checkpoint> Checkpointed<C> {
+    pub async fn restore(mut inner: C, store: impl Store + 'static, key: impl Into<String>) -> anyhow::Result<Self> {
+        let key = key.into();
+
+        if let Some(bytes) = store.load(&key).await? {
+            inner.restore(bincode::deserialize(&bytes)?);
+        }
+
+        Ok(Self {
+            inner,
+            store: Arc::new(store),
+            key,
+            every: Duration::from_secs(5),
+            saved: Instant::now(),
+            updates: Vec::new(),
+        })
+    }
+
+    /// How often a pusher saves, 5 seconds by default
+    pub fn every(mut self, every: Duration) -> Self {
+        self.every = every;
+        self
+    }
+
+    pub async fn save(&mut self) -> anyhow::Result<()> {
+        let bytes = bincode::serialize(&self.inner.snapshot())?;
+
+        self.store.save(&self.key, &bytes).await?;
+        self.saved = Instant::now();
+
+        Ok(())
+    }
+
+    /// Runs the state updates that came in, then saves
+    async fn apply(&mut self) -> anyhow::Result<()> {
+        if self.updates.is_empty() {
+            return Ok(());
+        }
+
+        for update in std::mem::take(&mut self.updates) {
+            update(&mut self.inner).await?;
+        }
+
+        self.save().await
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: Checkpoint> Drop for Checkpointed<C> {
+    fn drop(&mut self) {
+        // nothing can be awaited here, and outside of a runtime a file store can't save at all
+        if tokio::runtime::Handle::try_current().is_err() {
+            log::error!("saving checkpoint {}: no runtime left to save on", self.key);
+            return;
+        }
+
+        let saved = bincode::serialize(&self.inner.snapshot())
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| futures::executor::block_on(self.store.save(&self.key, &bytes)));
+
+        if let Err(e) = saved {
+            log::error!("saving checkpoint {}: {e:#}", self.key);
+        }
+    }
+}
+
+impl<C: Checkpoint + Poll + Send> Poll for Checkpointed<C> {
+    type Item = C::Item;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        self.apply().await?;
+
+        let res = self.inner.poll(tx).await;
+        self.save().await?;
+
+        res
+    }
+}
+
+impl<T: Send + 'static, C: Checkpoint + Push<T> + Send> Push<T> for Checkpointed<C> {
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        self.apply().await?;
+        self.inner.push(item).await?;
+
+        if self.saved.elapsed() >= self.every {
+            self.save().await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Send + 'static, C: Checkpoint + AsyncState<S>> State<S> for Checkpointed<C> {
+    fn update(&mut self, state: S) {
+        // a plain `AsyncState` impl would overlap the one every `State` gets
+        self.updates.push(Box::new(move |inner: &mut C| Box::pin(inner.update(state))));
+    }
+}
@@ -0,0 +1,185 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+use anyhow::Context;
+pub use bytes::Bytes;
+#[cfg(any(feature = "serde-json", feature = "bincode", feature = "cbor"))]
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::channel::{Channel, Outlet, Receiver, SendError, Sender};
+use crate::io::Poll;
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// Turns items into bytes and back
+pub trait Codec<T> {
+    fn encode(&self, item: &T) -> anyhow::Result<Bytes>;
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T>;
+}
+
+/// Longest frame a reader takes by default, 8 MiB
+pub(crate) const MAX_FRAME: usize = 8 << 20;
+
+/// How items are cut out of a byte stream
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// One item per line, a trailing `\r` is dropped when reading
+    #[default]
+    Lines,
+    /// A big endian `u32` length before every item
+    LengthDelimited,
+}
+
+impl Framing {
+    /// The first whole frame in `buf` and how many bytes it takes up there
+    ///
+    /// A frame longer than `max` bytes is an error, as soon as that much of it is in.
+    pub fn split(self, buf: &[u8], max: usize) -> anyhow::Result<Option<(&[u8], usize)>> {
+        match self {
+            Self::Lines => {
+                let Some(end) = buf.iter().take(max.saturating_add(1)).position(|&b| b == b'\n') else {
+                    anyhow::ensure!(buf.len() <= max, "line longer than {max} bytes");
+                    return Ok(None);
+                };
+
+                let line = &buf[..end];
+                Ok(Some((line.strip_suffix(b"\r").unwrap_or(line), end + 1)))
+            },
+            Self::LengthDelimited => {
+                let Some(len) = buf.get(..4) else {
+                    return Ok(None);
+                };
+
+                let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+                anyhow::ensure!(len <= max, "frame of {len} bytes, longer than {max}");
+
+                Ok(buf.get(4..4 + len).map(|frame| (frame, 4 + len)))
+            },
+        }
+    }
+
+    /// The last frame once the input ended with `buf` left over, a last line doesn't
+    /// need its newline but a record cut short is an error
+    pub fn finish(self, buf: &[u8]) -> anyhow::Result<Option<&[u8]>> {
+        match self {
+            _ if buf.is_empty() => Ok(None),
+            Self::Lines => Ok(Some(buf.strip_suffix(b"\r").unwrap_or(buf))),
+            Self::LengthDelimited => anyhow::bail!("input ends in the middle of a frame"),
+        }
+    }
+}
+
+/// Strings as UTF-8, the bytes are checked when decoding
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Text;
+
+impl Codec<String> for Text {
+    fn encode(&self, item: &String) -> anyhow::Result<Bytes> {
+        Ok(Bytes::copy_from_slice(item.as_bytes()))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<String> {
+        Ok(std::str::from_utf8(bytes)?.to_owned())
+    }
+}
+
+#[cfg(feature = "serde-json")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+#[cfg(feature = "serde-json")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for Json {
+    fn encode(&self, item: &T) -> anyhow::Result<Bytes> {
+        Ok(serde_json::to_vec(item)?.into())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+#[cfg(feature = "bincode")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+#[cfg(feature = "bincode")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for Bincode {
+    fn encode(&self, item: &T) -> anyhow::Result<Bytes> {
+        Ok(bincode::serialize(item)?.into())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for Cbor {
+    fn encode(&self, item: &T) -> anyhow::Result<Bytes> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(item, &mut bytes)?;
+
+        Ok(bytes.into())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
+
+/// Encodes every item from a receiver, made by [`encode`]
+pub struct Encode<T, C> {
+    recver: Receiver<T>,
+    codec: C,
+}
+
+/// Encodes everything from `rx` with `codec`, an item that fails to encode stops the stage
+pub fn encode<T: Send, C: Codec<T> + Send>(rx: Receiver<T>, codec: C) -> (Poller<Encode<T, C>>, Receiver<Bytes>) {
+    let (outlet, out) = Outlet::new(Channel::bounded(1));
+
+    (Poller::new(Encode { recver: rx, codec }, outlet), out)
+}
+
+impl<T: Send, C: Codec<T> + Send> Poll for Encode<T, C> {
+    type Item = Bytes;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            let item = self.recver.recv().await.map_err(|e| Internal(e.into()))?;
+            let bytes = self.codec.encode(&item).context("encoding item")?;
+
+            tx.send(bytes).await.map_err(|_| Internal(SendError(()).into()))?;
+        }
+    }
+}
+
+/// Decodes every buffer from a receiver, made by [`decode`]
+pub struct Decode<T, C> {
+    recver: Receiver<Bytes>,
+    codec: C,
+    _item: PhantomData<fn() -> T>,
+}
+
+/// Decodes everything from `rx` with `codec`, a buffer that fails to decode stops the stage
+pub fn decode<T: Send, C: Codec<T> + Send>(rx: Receiver<Bytes>, codec: C) -> (Poller<Decode<T, C>>, Receiver<T>) {
+    let (outlet, out) = Outlet::new(Channel::bounded(1));
+
+    (Poller::new(Decode { recver: rx, codec, _item: PhantomData }, outlet), out)
+}
+
+impl<T: Send, C: Codec<T> + Send> Poll for Decode<T, C> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            let bytes = self.recver.recv().await.map_err(|e| Internal(e.into()))?;
+            let item = self.codec.decode(&bytes).context("decoding item")?;
+
+            tx.send(item).await.map_err(|_| Internal(SendError(()).into()))?;
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+/// `item` as a length prefixed record
+pub fn encode<T: Serialize>(item: &T) -> anyhow::Result<Vec<u8>> {
+    let bytes = bincode::serialize(item)?;
+
+    Ok([&(bytes.len() as u32).to_le_bytes()[..], &bytes].concat())
+}
+
+/// Reads the next record written by [`encode`], `None` at the end or at a torn trailing record
+pub async fn read_next<T: DeserializeOwned>(r: &mut (impl AsyncRead + Unpin)) -> anyhow::Result<Option<T>> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len).await {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    };
+
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    match r.read_exact(&mut bytes).await {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    };
+
+    Ok(Some(bincode::deserialize(&bytes)?))
+}
+
+/// Reads back every record in `path`
+pub async fn read_all<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let mut r = BufReader::new(tokio::fs::File::open(path).await?);
+    let mut items = Vec::new();
+
+    while let Some(item) = read_next(&mut r).await? {
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+/// Segment files in `dir` with the given extension, oldest first
+pub async fn segments(dir: &Path, ext: &str) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if path.extension().and_then(|e| e.to_str()) != Some(ext) {
+            continue;
+        }
+
+        if let Some(seq) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+            segments.push((seq, path));
+        }
+    }
+
+    segments.sort();
+
+    Ok(segments)
+}
+
+pub fn segment_path(dir: &Path, seq: u64, ext: &str) -> PathBuf {
+    dir.join(format!("{seq:020}.{ext}"))
+}
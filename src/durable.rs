@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::time::Duration;
+
+use futures::future::{select, BoxFuture, Either};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::{timeout_at, Instant};
+
+use crate::channel::{Receiver, RecvError};
+use crate::disk;
+use crate::io::Push;
+use crate::Error::*;
+
+const EXT: &str = "log";
+const OFFSET: &str = "offset";
+
+/// A write-ahead log in front of a pusher, for at-least-once delivery across restarts
+///
+/// Every item is appended to a log in `dir` before it's handed to the pusher, and the
+/// consumer offset moves on once [`Push::push`] returns `Ok`. Offsets are committed in
+/// batches, see [`Durable::commit_every`]. On start, anything logged but not committed
+/// by a previous run is pushed again first, so a pusher should expect up to a batch of
+/// duplicates after a crash.
+pub struct Durable<T> {
+    recver: Receiver<T>,
+    dir: PathBuf,
+    memory: usize,
+    segment: usize,
+    sync: bool,
+    batch: usize,
+    interval: Duration,
+}
+
+/// Logs everything from `rx` to `dir`, see [`Durable`]
+pub fn durable<T>(rx: Receiver<T>, dir: impl Into<PathBuf>) -> Durable<T> {
+    Durable {
+        recver: rx,
+        dir: dir.into(),
+        memory: 1024,
+        segment: 4096,
+        sync: false,
+        batch: 64,
+        interval: Duration::from_secs(1),
+    }
+}
+
+impl<T> Durable<T> {
+    /// Pending items kept in memory, the rest are read back from the log, 1024 by default
+    pub fn memory(mut self, items: usize) -> Self {
+        self.memory = items.max(1);
+        self
+    }
+
+    /// Items per log segment, 4096 by default
+    pub fn segment(mut self, items: usize) -> Self {
+        self.segment = items.max(1);
+        self
+    }
+
+    /// `fsync` every append and commit, so items also survive power loss
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Commits the offset once `items` were pushed, or `interval` after the first of them
+    ///
+    /// 64 items and 1s by default. `commit_every(1, Duration::ZERO)` commits after every push.
+    pub fn commit_every(mut self, items: usize, interval: Duration) -> Self {
+        self.batch = items.max(1);
+        self.interval = interval;
+        self
+    }
+
+    pub fn to<P: Push<T>>(self, p: P) -> DurablePusher<T, P> {
+        DurablePusher { durable: self, pusher: p }
+    }
+}
+
+pub struct DurablePusher<T, P> {
+    durable: Durable<T>,
+    pusher: P,
+}
+
+struct Segment {
+    first: u64,
+    path: PathBuf,
+}
+
+struct Log<T> {
+    dir: PathBuf,
+    memory: usize,
+    segment: usize,
+    sync: bool,
+    batch: usize,
+    interval: Duration,
+    segments: VecDeque<Segment>,
+    writer: Option<(BufWriter<File>, usize)>,
+    /// items from `read` onwards, when `cached` they reach all the way to `next`
+    head: VecDeque<T>,
+    cached: bool,
+    /// pushed so far, `committed` is how far the offset on disk got
+    acked: u64,
+    committed: u64,
+    /// when the first item past `committed` was pushed
+    since: Option<Instant>,
+    read: u64,
+    next: u64,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Log<T> {
+    async fn open(durable: &Durable<T>) -> anyhow::Result<Self> {
+        let dir = durable.dir.clone();
+        fs::create_dir_all(&dir).await?;
+
+        let committed = match fs::read(dir.join(OFFSET)).await {
+            Ok(bytes) => u64::from_le_bytes(bytes.as_slice().try_into()?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut segments: VecDeque<_> = disk::segments(&dir, EXT).await?
+            .into_iter()
+            .map(|(first, path)| Segment { first, path })
+            .collect();
+
+        // appends always go to a fresh segment, past whatever a crash may have torn
+        let mut next = 0;
+
+        while let Some(last) = segments.back() {
+            let count = disk::read_all::<T>(&last.path).await?.len() as u64;
+
+            if count > 0 {
+                next = last.first + count;
+                break;
+            }
+
+            // nothing readable in it, the fresh segment would take over its name
+            next = last.first;
+            fs::remove_file(&segments.pop_back().unwrap().path).await?;
+        }
+
+        anyhow::ensure!(committed <= next, "committed offset {committed} is past the end of the log at {next}");
+
+        let mut log = Self {
+            dir,
+            memory: durable.memory,
+            segment: durable.segment,
+            sync: durable.sync,
+            batch: durable.batch,
+            interval: durable.interval,
+            segments: VecDeque::new(),
+            writer: None,
+            head: VecDeque::new(),
+            cached: committed == next,
+            acked: committed,
+            committed,
+            since: None,
+            read: committed,
+            next,
+            _item: PhantomData,
+        };
+
+        log.segments.append(&mut segments);
+        log.prune().await?;
+
+        Ok(log)
+    }
+
+    async fn append(&mut self, item: T) -> anyhow::Result<()> {
+        if self.writer.is_none() {
+            let path = disk::segment_path(&self.dir, self.next, EXT);
+            let file = File::create(&path).await?;
+
+            self.segments.push_back(Segment { first: self.next, path });
+            self.writer = Some((BufWriter::new(file), 0));
+        }
+
+        let (w, count) = self.writer.as_mut().unwrap();
+        w.write_all(&disk::encode(&item)?).await?;
+        w.flush().await?;
+
+        if self.sync {
+            w.get_ref().sync_data().await?;
+        }
+
+        *count += 1;
+        if *count >= self.segment {
+            self.writer = None;
+        }
+
+        self.next += 1;
+
+        if self.cached && self.head.len() < self.memory {
+            self.head.push_back(item);
+        } else {
+            self.cached = false;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the next item to push, it stays in the log until [`Log::ack`] and [`Log::commit`]
+    async fn take(&mut self) -> anyhow::Result<Option<T>> {
+        if self.head.is_empty() {
+            if self.read == self.next {
+                self.cached = true;
+            } else {
+                self.load().await?;
+            }
+        }
+
+        let item = self.head.pop_front();
+        if item.is_some() {
+            self.read += 1;
+        }
+
+        Ok(item)
+    }
+
+    async fn load(&mut self) -> anyhow::Result<()> {
+        for segment in self.segments.iter().rev() {
+            if segment.first > self.read {
+                continue;
+            }
+
+            let skip = (self.read - segment.first) as usize;
+            let items = disk::read_all::<T>(&segment.path).await?;
+
+            self.head.extend(items.into_iter().skip(skip).take(self.memory));
+            break;
+        }
+
+        self.cached = self.read + self.head.len() as u64 == self.next;
+        anyhow::ensure!(!self.head.is_empty(), "log is missing item {}", self.read);
+
+        Ok(())
+    }
+
+    /// The item taken last was pushed
+    fn ack(&mut self) {
+        self.acked += 1;
+        self.since.get_or_insert_with(Instant::now);
+    }
+
+    /// When the pushed items should be committed, `None` while there are none
+    fn due(&self) -> Option<Instant> {
+        let since = self.since?;
+
+        if self.acked - self.committed >= self.batch as u64 {
+            Some(since)
+        } else {
+            Some(since + self.interval)
+        }
+    }
+
+    async fn commit(&mut self) -> anyhow::Result<()> {
+        if self.acked == self.committed {
+            return Ok(());
+        }
+
+        let tmp = self.dir.join(format!("{OFFSET}.tmp"));
+        let mut file = File::create(&tmp).await?;
+        file.write_all(&self.acked.to_le_bytes()).await?;
+        file.flush().await?;
+
+        if self.sync {
+            file.sync_data().await?;
+        }
+
+        fs::rename(tmp, self.dir.join(OFFSET)).await?;
+
+        self.committed = self.acked;
+        self.since = None;
+        self.prune().await
+    }
+
+    /// Removes segments that have been pushed in full
+    async fn prune(&mut self) -> anyhow::Result<()> {
+        while self.segments.len() > 1 && self.segments[1].first <= self.committed {
+            fs::remove_file(self.segments.pop_front().unwrap().path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, P> IntoFuture for DurablePusher<T, P>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+    P: Push<T> + Send + 'static,
+{
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { durable, mut pusher } = self;
+
+        Box::pin(async move {
+            let mut log = Log::open(&durable).await.map_err(User)?;
+            let recver = durable.recver;
+            let mut closed = false;
+
+            loop {
+                let Some(item) = log.take().await.map_err(User)? else {
+                    if closed {
+                        log.commit().await.map_err(User)?;
+                        return Err(Internal(RecvError.into()));
+                    }
+
+                    // caught up, so a pending batch is committed once it's due rather than left waiting on input
+                    let recv = match log.due() {
+                        Some(due) => match timeout_at(due, recver.recv()).await {
+                            Ok(recv) => recv,
+                            Err(_) => {
+                                log.commit().await.map_err(User)?;
+                                continue;
+                            },
+                        },
+                        None => recver.recv().await,
+                    };
+
+                    match recv {
+                        Ok(item) => log.append(item).await.map_err(User)?,
+                        Err(_) => closed = true,
+                    }
+
+                    continue;
+                };
+
+                let mut pushing = pin!(pusher.push(item));
+
+                // keep logging while the pusher works, so upstream never waits on it
+                let pushed = loop {
+                    if closed {
+                        break pushing.as_mut().await;
+                    }
+
+                    match select(pin!(recver.recv()), pushing.as_mut()).await {
+                        Either::Left((Ok(item), _)) => log.append(item).await.map_err(User)?,
+                        Either::Left((Err(_), _)) => closed = true,
+                        Either::Right((res, _)) => break res,
+                    }
+                };
+
+                if let Err(e) = pushed {
+                    // the push error is the one worth reporting, the commit only saves redelivery
+                    let _ = log.commit().await;
+                    return Err(User(e));
+                }
+
+                log.ack();
+
+                if log.due().is_some_and(|due| due <= Instant::now()) {
+                    log.commit().await.map_err(User)?;
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::time::Duration;
+
+use futures::future::{select, Either};
+use glob::Pattern;
+use tokio::io::unix::AsyncFd;
+use tokio::time::{sleep_until, Instant};
+
+use crate::channel;
+use crate::io::Poll;
+
+/// Something happened to a file or directory under a [`FsWatch`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsEvent {
+    pub path: PathBuf,
+    pub kind: FsEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FsEventKind {
+    Created,
+    Modified,
+    Deleted,
+    /// moved within the watched tree, moves in and out show up as created and deleted
+    Renamed { from: PathBuf },
+    /// the kernel dropped events, `path` is the root and anything under it may have changed
+    ///
+    /// Rescan the tree rather than trusting earlier events. Directories created in the
+    /// meantime are watched from here on. Filters don't apply to it.
+    Overflow,
+}
+
+/// Watches a directory with inotify
+///
+/// Events for the same path within the coalescing window are merged into one, a file
+/// created and written shows up as created, one created and deleted not at all.
+pub struct FsWatch {
+    root: PathBuf,
+    recursive: bool,
+    filters: Vec<Pattern>,
+    coalesce: Duration,
+}
+
+impl FsWatch {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            recursive: true,
+            filters: Vec::new(),
+            coalesce: Duration::from_millis(100),
+        }
+    }
+
+    /// Whether to watch subdirectories too, on by default
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Only reports paths matching `glob` relative to the root, like `**/*.toml`,
+    /// any of several filters may match
+    pub fn filter(mut self, glob: &str) -> anyhow::Result<Self> {
+        self.filters.push(Pattern::new(glob)?);
+        Ok(self)
+    }
+
+    /// How long to gather events before emitting them, 100ms by default
+    pub fn coalesce(mut self, window: Duration) -> Self {
+        self.coalesce = window;
+        self
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        self.filters.is_empty() || self.filters.iter().any(|p| p.matches_path(relative))
+    }
+}
+
+const MASK: u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MODIFY | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+
+struct Inotify {
+    fd: AsyncFd<OwnedFd>,
+    dirs: HashMap<i32, PathBuf>,
+}
+
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        // SAFETY: plain syscall, the fd is owned from here on
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: AsyncFd::new(unsafe { OwnedFd::from_raw_fd(fd) })?,
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// Watches `dir`, and everything below it when `recursive`, returning what's in there
+    fn watch(&mut self, dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        let cpath = CString::new(dir.as_os_str().as_bytes())?;
+
+        // SAFETY: a valid nul terminated path
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), cpath.as_ptr(), MASK | libc::IN_ONLYDIR) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.dirs.insert(wd, dir.to_owned());
+
+        if recursive {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                found.push(entry.path());
+
+                if entry.file_type()?.is_dir() {
+                    // it may be gone again already
+                    let _ = self.watch(&entry.path(), recursive, found);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Watches the tree again after events were lost, dropping watches on what's no longer in it
+    fn rescan(&mut self, root: &Path, recursive: bool) -> io::Result<()> {
+        let old = std::mem::take(&mut self.dirs);
+
+        // watching a directory again hands back the same descriptor
+        self.watch(root, recursive, &mut Vec::new())?;
+
+        for wd in old.into_keys().filter(|wd| !self.dirs.contains_key(wd)) {
+            // SAFETY: plain syscall on our own fd
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+
+        Ok(())
+    }
+
+    /// Follows a directory moved to `to`, or forgets it when it left the tree
+    fn moved(&mut self, from: &Path, to: Option<&Path>) {
+        let moved: Vec<_> = self.dirs.iter().filter(|(_, dir)| dir.starts_with(from)).map(|(&wd, _)| wd).collect();
+
+        for wd in moved {
+            match to {
+                Some(to) => {
+                    let dir = self.dirs.get_mut(&wd).unwrap();
+                    *dir = to.join(dir.strip_prefix(from).unwrap());
+                }
+                None => {
+                    self.dirs.remove(&wd);
+
+                    // SAFETY: plain syscall on our own fd
+                    unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+                }
+            }
+        }
+    }
+
+    async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+
+            // SAFETY: reading into a buffer we own
+            let res = guard.try_io(|fd| match unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) } {
+                n if n < 0 => Err(io::Error::last_os_error()),
+                n => Ok(n as usize),
+            });
+
+            if let Ok(res) = res {
+                return res;
+            }
+        }
+    }
+}
+
+/// Raw events, before pairing renames and coalescing
+struct Raw {
+    path: PathBuf,
+    mask: u32,
+    cookie: u32,
+}
+
+fn parse(buf: &[u8], dirs: &HashMap<i32, PathBuf>, out: &mut Vec<(i32, Raw)>) {
+    let mut at = 0;
+
+    while at + size_of::<libc::inotify_event>() <= buf.len() {
+        // SAFETY: the kernel wrote whole events, read unaligned since `buf` is bytes
+        let event: libc::inotify_event = unsafe { buf.as_ptr().add(at).cast::<libc::inotify_event>().read_unaligned() };
+        let name_at = at + size_of::<libc::inotify_event>();
+        let name = &buf[name_at..name_at + event.len as usize];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+        if event.mask & libc::IN_Q_OVERFLOW != 0 {
+            out.push((event.wd, Raw { path: PathBuf::new(), mask: event.mask, cookie: event.cookie }));
+        } else if let Some(dir) = dirs.get(&event.wd) {
+            let path = if name.is_empty() { dir.clone() } else { dir.join(OsStr::from_bytes(name)) };
+            out.push((event.wd, Raw { path, mask: event.mask, cookie: event.cookie }));
+        }
+
+        at = name_at + event.len as usize;
+    }
+}
+
+/// Folds `event` into the pending event for the same path
+fn coalesce(pending: &mut Vec<FsEvent>, event: FsEvent) {
+    use FsEventKind::*;
+
+    let mut event = event;
+
+    // created and moved into place, like a download finishing
+    if let Renamed { from } = &event.kind {
+        if let Some(i) = pending.iter().position(|e| &e.path == from && e.kind == Created) {
+            pending.remove(i);
+            event.kind = Created;
+        }
+    }
+
+    let Some(i) = pending.iter().position(|e| e.path == event.path) else {
+        pending.push(event);
+        return;
+    };
+
+    let merged = match (&pending[i].kind, event.kind) {
+        (Created, Modified) => Some(Created),
+        (Created, Deleted) => None,
+        (Renamed { from }, Modified) => Some(Renamed { from: from.clone() }),
+        (Deleted, Created) => Some(Modified),
+        (_, kind) => Some(kind),
+    };
+
+    match merged {
+        Some(kind) => pending[i].kind = kind,
+        None => drop(pending.remove(i)),
+    }
+}
+
+impl Poll for FsWatch {
+    type Item = FsEvent;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut inotify = Inotify::new()?;
+        inotify.watch(&self.root, self.recursive, &mut Vec::new())?;
+
+        let mut buf = vec![0; 64 << 10];
+        let mut raw = Vec::new();
+        let mut pending: Vec<FsEvent> = Vec::new();
+        let mut deadline: Option<Instant> = None;
+
+        loop {
+            let n = match deadline {
+                Some(deadline) => {
+                    let read = pin!(inotify.read(&mut buf));
+                    let timer = pin!(sleep_until(deadline));
+
+                    match select(read, timer).await {
+                        Either::Left((n, _)) => Some(n?),
+                        Either::Right(_) => None,
+                    }
+                }
+                None => Some(inotify.read(&mut buf).await?),
+            };
+
+            let Some(n) = n else {
+                for event in pending.drain(..) {
+                    tx.send(event).await.map_err(|_| channel::SendError(()))?;
+                }
+
+                deadline = None;
+                continue;
+            };
+
+            parse(&buf[..n], &inotify.dirs, &mut raw);
+
+            let mut events = Vec::new();
+            let mut moved_from: Vec<Raw> = Vec::new();
+            let mut overflowed = false;
+
+            for (wd, event) in raw.drain(..) {
+                let is_dir = event.mask & libc::IN_ISDIR != 0;
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    inotify.rescan(&self.root, self.recursive)?;
+                    overflowed = true;
+                    continue;
+                }
+
+                if event.mask & libc::IN_IGNORED != 0 {
+                    inotify.dirs.remove(&wd);
+                    continue;
+                }
+
+                if event.mask & libc::IN_MOVED_FROM != 0 {
+                    moved_from.push(event);
+                    continue;
+                }
+
+                let kind = if event.mask & libc::IN_MOVED_TO != 0 {
+                    match moved_from.iter().position(|m| m.cookie == event.cookie) {
+                        Some(i) => FsEventKind::Renamed { from: moved_from.remove(i).path },
+                        None => FsEventKind::Created,
+                    }
+                } else if event.mask & libc::IN_CREATE != 0 {
+                    FsEventKind::Created
+                } else if event.mask & libc::IN_DELETE != 0 {
+                    FsEventKind::Deleted
+                } else if event.mask & libc::IN_MODIFY != 0 {
+                    FsEventKind::Modified
+                } else {
+                    continue;
+                };
+
+                let path = event.path.clone();
+                events.push(FsEvent { path: event.path, kind: kind.clone() });
+
+                if !is_dir || !self.recursive {
+                    continue;
+                }
+
+                match kind {
+                    // the watches below keep going, only their paths change
+                    FsEventKind::Renamed { from } => inotify.moved(&from, Some(&path)),
+                    // new directories get watched too, along with whatever landed in them already
+                    FsEventKind::Created => {
+                        let mut found = Vec::new();
+                        let _ = inotify.watch(&path, true, &mut found);
+                        events.extend(found.into_iter().map(|path| FsEvent { path, kind: FsEventKind::Created }));
+                    }
+                    _ => {}
+                }
+            }
+
+            // moved out of the tree
+            for event in moved_from {
+                if event.mask & libc::IN_ISDIR != 0 {
+                    inotify.moved(&event.path, None);
+                }
+
+                events.push(FsEvent { path: event.path, kind: FsEventKind::Deleted });
+            }
+
+            for event in events {
+                if self.matches(&event.path) {
+                    coalesce(&mut pending, event);
+                }
+            }
+
+            if overflowed {
+                coalesce(&mut pending, FsEvent { path: self.root.clone(), kind: FsEventKind::Overflow });
+            }
+
+            if !pending.is_empty() && deadline.is_none() {
+                deadline = Some(Instant::now() + self.coalesce);
+            }
+        }
+    }
+}
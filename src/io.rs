@@ -2,7 +2,7 @@ use std::convert::Infallible;
 
 use futures::Future;
 
-use crate::channel::{unbounded, Receiver, self};
+use crate::channel::{self, Channel, Outlet, Receiver};
 use crate::pollers::{IntoPoller, Poller};
 use crate::pushers::EmptyPusher;
 
@@ -10,6 +10,31 @@ pub trait State<T> {
     fn update(&mut self, state: T);
 }
 
+/// Fallible version of [`State`], for stages that need to do some work
+/// (reconnecting, validating) before a new state can take effect
+pub trait AsyncState<T> {
+    fn update(&mut self, state: T) -> impl Future<Output = anyhow::Result<()>> + Send + '_;
+}
+
+impl<T, S: State<T>> AsyncState<T> for S {
+    fn update(&mut self, state: T) -> impl Future<Output = anyhow::Result<()>> + Send + '_ {
+        State::update(self, state);
+        std::future::ready(Ok(()))
+    }
+}
+
+/// What a stateful stage does when [`AsyncState::update`] returns an error
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rejected {
+    /// Drop the error and keep going
+    Ignore,
+    /// Apply the last accepted state again
+    KeepPrevious,
+    /// Stop the stage with the error
+    #[default]
+    Fail,
+}
+
 pub trait Poll {
     /// What you are sending to the rest of the app
     type Item: Send;
@@ -19,9 +44,9 @@ pub trait Poll {
 }
 
 pub fn poll<P: Poll>(p: impl IntoPoller<P>) -> (Poller<P>, Receiver<P::Item>) {
-    let (tx, rx) = unbounded();
+    let (outlet, rx) = Outlet::new(Channel::default());
 
-    (Poller::new(p.into_poller(), tx), rx)
+    (Poller::new(p.into_poller(), outlet), rx)
 }
 
 pub trait Push<T> {
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::pin::pin;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::{select, Either};
+use futures::FutureExt;
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+
+use crate::channel::{self, bounded, Channel, Outlet, Receiver, Sender};
+use crate::io::Poll;
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// A key's sub-pipeline was shut down, made by [`per_key`]
+#[derive(Debug)]
+pub struct Retired<K> {
+    pub key: K,
+    /// why it stopped, `None` when it was idle or evicted and finished what it was given
+    pub error: Option<crate::Error>,
+}
+
+/// Routes items into a sub-pipeline per key, see [`per_key`]
+pub struct PerKey<T, K, KF, F> {
+    recver: Receiver<T>,
+    key_fn: KF,
+    idle: Duration,
+    factory: F,
+    max: usize,
+    buffer: usize,
+    _key: std::marker::PhantomData<fn() -> K>,
+}
+
+/// Builds a sub-pipeline with `factory` the first time `key_fn` returns a key, and
+/// routes every item with that key into it
+///
+/// A sub-pipeline that got nothing for `idle` is shut down, and so is the least
+/// recently used one when a new key would go past `max_keys`, by closing its input
+/// so that it finishes the items queued for it. Each runs as its own task, one failing
+/// doesn't affect the others, and the next item for its key builds a new one, also
+/// when it is still draining. Retired sub-pipelines come out of the returned receiver.
+#[allow(clippy::type_complexity)]
+pub fn per_key<T, K, KF, F, P>(rx: Receiver<T>, key_fn: KF, idle: Duration, factory: F) -> (Poller<PerKey<T, K, KF, F>>, Receiver<Retired<K>>)
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+    KF: Fn(&T) -> K + Send + Sync,
+    F: Fn(&K, Receiver<T>) -> P + Send + Sync,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>> + 'static,
+    P::IntoFuture: Send + 'static,
+{
+    let per_key = PerKey {
+        recver: rx,
+        key_fn,
+        idle,
+        factory,
+        max: usize::MAX,
+        buffer: 64,
+        _key: std::marker::PhantomData,
+    };
+
+    let (outlet, rx) = Outlet::new(Channel::default());
+
+    (Poller::new(per_key, outlet), rx)
+}
+
+impl<T, K, KF, F, P> Poller<PerKey<T, K, KF, F>>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+    KF: Fn(&T) -> K + Send + Sync,
+    F: Fn(&K, Receiver<T>) -> P + Send + Sync,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>> + 'static,
+    P::IntoFuture: Send + 'static,
+{
+    /// Most sub-pipelines alive at once, unlimited by default
+    pub fn max_keys(self, keys: usize) -> Self {
+        self.map_poller(|p| PerKey { max: keys.max(1), ..p })
+    }
+
+    /// Items queued for each sub-pipeline, 64 by default
+    ///
+    /// A sub-pipeline with a full queue holds up routing for every key.
+    pub fn buffer(self, items: usize) -> Self {
+        self.map_poller(|p| PerKey { buffer: items.max(1), ..p })
+    }
+}
+
+struct Live<T> {
+    sender: Sender<T>,
+    seen: Instant,
+    generation: u64,
+}
+
+impl<T, K, KF, F, P> Poll for PerKey<T, K, KF, F>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+    KF: Fn(&T) -> K + Send + Sync,
+    F: Fn(&K, Receiver<T>) -> P + Send + Sync,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>> + 'static,
+    P::IntoFuture: Send + 'static,
+{
+    type Item = Retired<K>;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut live: HashMap<K, Live<T>> = HashMap::new();
+        let mut tasks = JoinSet::new();
+        let mut generation = 0;
+        // sub-pipelines shut down by closing their input, by generation
+        let mut draining = HashSet::new();
+
+        loop {
+            let deadline = live.values().map(|l| l.seen + self.idle).min();
+
+            let event = {
+                let recv = pin!(self.recver.recv());
+                let others = pin!(async {
+                    match deadline {
+                        Some(deadline) if tasks.is_empty() => {
+                            sleep_until(deadline).await;
+                            Either::Left(())
+                        },
+                        Some(deadline) => match select(pin!(sleep_until(deadline)), pin!(tasks.join_next())).await {
+                            Either::Left(_) => Either::Left(()),
+                            Either::Right((joined, _)) => Either::Right(joined),
+                        },
+                        None if tasks.is_empty() => std::future::pending().await,
+                        None => Either::Right(tasks.join_next().await),
+                    }
+                });
+
+                match select(recv, others).await {
+                    Either::Left((item, _)) => Either::Left(item),
+                    Either::Right((other, _)) => Either::Right(other),
+                }
+            };
+
+            let item = match event {
+                Either::Left(Ok(item)) => item,
+                Either::Left(Err(e)) => {
+                    // every sub-pipeline finishes what it was given before this ends like its input
+                    draining.extend(live.drain().map(|(_, l)| l.generation));
+
+                    while let Some(joined) = tasks.join_next().await {
+                        if let Ok(joined) = joined {
+                            tx.send(retired(&mut draining, joined)).await.map_err(|_| channel::SendError(()))?;
+                        }
+                    }
+
+                    return Err(Internal(e.into()).into());
+                },
+                Either::Right(Either::Left(())) => {
+                    let now = Instant::now();
+                    let idle: Vec<_> = live.iter().filter(|(_, l)| l.seen + self.idle <= now).map(|(k, _)| k.clone()).collect();
+
+                    for key in idle {
+                        draining.insert(live.remove(&key).unwrap().generation);
+                    }
+
+                    continue;
+                },
+                Either::Right(Either::Right(joined)) => {
+                    let Some(Ok(joined)) = joined else {
+                        continue;
+                    };
+
+                    if live.get(&joined.0).is_some_and(|l: &Live<T>| l.generation == joined.1) {
+                        live.remove(&joined.0);
+                    }
+
+                    tx.send(retired(&mut draining, joined)).await.map_err(|_| channel::SendError(()))?;
+                    continue;
+                },
+            };
+
+            let key = (self.key_fn)(&item);
+            let mut item = item;
+
+            loop {
+                if !live.contains_key(&key) {
+                    if live.len() >= self.max {
+                        let lru = live.iter().min_by_key(|(_, l)| l.seen).map(|(k, _)| k.clone()).unwrap();
+                        draining.insert(live.remove(&lru).unwrap().generation);
+                    }
+
+                    generation += 1;
+                    live.insert(key.clone(), self.spawn(&mut tasks, &key, generation));
+                }
+
+                let entry = live.get_mut(&key).unwrap();
+                entry.seen = Instant::now();
+
+                // a sub-pipeline that stopped reading failed or ended, its task reports why
+                // once joined and the item goes to a new one
+                match entry.sender.send(item).await {
+                    Ok(()) => break,
+                    Err(channel::SendError(back)) => {
+                        live.remove(&key);
+                        item = back;
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl<T, K, KF, F, P> PerKey<T, K, KF, F>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+    KF: Fn(&T) -> K + Send + Sync,
+    F: Fn(&K, Receiver<T>) -> P + Send + Sync,
+    P: IntoFuture<Output = Result<Infallible, crate::Error>> + 'static,
+    P::IntoFuture: Send + 'static,
+{
+    /// Builds the sub-pipeline for `key` and runs it on `tasks`
+    fn spawn(&self, tasks: &mut JoinSet<(K, u64, crate::Error)>, key: &K, gen: u64) -> Live<T> {
+        let (sender, rx) = bounded(self.buffer);
+        let pipeline = (self.factory)(key, rx).into_future();
+        let key = key.clone();
+
+        tasks.spawn(async move {
+            let error = match AssertUnwindSafe(pipeline).catch_unwind().await {
+                Ok(Err(error)) => error,
+                Ok(Ok(never)) => match never {},
+                Err(_) => Internal(anyhow!("pipeline panicked")),
+            };
+
+            (key, gen, error)
+        });
+
+        Live { sender, seen: Instant::now(), generation: gen }
+    }
+}
+
+/// The report for a joined sub-pipeline
+fn retired<K>(draining: &mut HashSet<u64>, (key, gen, error): (K, u64, crate::Error)) -> Retired<K> {
+    let error = match (draining.remove(&gen), error) {
+        // it ran out of input, as it was told to
+        (true, Internal(_)) => None,
+        (_, error) => Some(error),
+    };
+
+    Retired { key, error }
+}
@@ -1,20 +1,95 @@
+mod budget;
 mod io;
 
 mod pollers;
 mod pushers;
 
+mod state;
+mod update;
 mod util;
 
-pub mod channel {
-    pub use async_channel::{
-        bounded, unbounded, Receiver, Recv, RecvError, Send, SendError, Sender,
-    };
-}
+#[cfg(feature = "tcp")]
+mod acceptor;
+#[cfg(feature = "checkpoint")]
+mod checkpoint;
+#[cfg(feature = "codec")]
+mod codec;
+#[cfg(feature = "disk")]
+mod disk;
+#[cfg(feature = "disk")]
+mod durable;
+#[cfg(all(feature = "fswatch", target_os = "linux"))]
+mod fswatch;
+#[cfg(feature = "per-key")]
+mod keyed;
+#[cfg(any(feature = "tcp", feature = "uds"))]
+mod link;
+#[cfg(feature = "process")]
+mod process;
+#[cfg(feature = "disk")]
+mod record;
+#[cfg(feature = "checkpoint")]
+mod snapshot;
+#[cfg(feature = "schedule")]
+mod schedule;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+mod shm;
+#[cfg(all(feature = "signal", unix))]
+mod signal;
+#[cfg(feature = "disk")]
+mod spill;
+#[cfg(feature = "stdio")]
+mod stdio;
+#[cfg(feature = "tail")]
+mod tail;
+#[cfg(feature = "tcp")]
+mod tcp;
+#[cfg(feature = "uds")]
+mod uds;
+
+pub mod channel;
 
 pub mod prelude {
+    pub use crate::budget::{Budget, ByteSize, OverBudget};
+    pub use crate::channel::Channel;
     pub use crate::io::*;
     pub use crate::pollers::*;
     pub use crate::pushers::*;
+    pub use crate::state::*;
+    #[cfg(feature = "tcp")]
+    pub use crate::acceptor::*;
+    #[cfg(feature = "checkpoint")]
+    pub use crate::checkpoint::*;
+    #[cfg(feature = "codec")]
+    pub use crate::codec::*;
+    #[cfg(feature = "disk")]
+    pub use crate::durable::*;
+    #[cfg(all(feature = "fswatch", target_os = "linux"))]
+    pub use crate::fswatch::*;
+    #[cfg(feature = "per-key")]
+    pub use crate::keyed::*;
+    #[cfg(feature = "process")]
+    pub use crate::process::*;
+    #[cfg(feature = "disk")]
+    pub use crate::record::*;
+    #[cfg(feature = "checkpoint")]
+    pub use crate::snapshot::*;
+    #[cfg(feature = "schedule")]
+    pub use crate::schedule::*;
+    #[cfg(all(feature = "shm", target_os = "linux"))]
+    pub use crate::shm::*;
+    #[cfg(all(feature = "signal", unix))]
+    pub use crate::signal::*;
+    #[cfg(feature = "disk")]
+    pub use crate::spill::*;
+    #[cfg(feature = "stdio")]
+    pub use crate::stdio::*;
+    #[cfg(feature = "tail")]
+    pub use crate::tail::*;
+    #[cfg(feature = "tcp")]
+    pub use crate::tcp::*;
+    #[cfg(feature = "uds")]
+    pub use crate::uds::*;
 
     pub use std::convert::Infallible; 
     pub use anyhow;
@@ -36,8 +111,23 @@ impl Error {
             Self::User(e) => e,
         }
     }
+
+    /// Maps an error a poller returned, a poller ends quietly by returning `Internal`
+    pub(crate) fn from_poll(e: anyhow::Error) -> Self {
+        e.downcast().unwrap_or_else(Self::User)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Internal(e) | Self::User(e) => e.fmt(f),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
 pub mod macro_helpers {
     use std::future::Future;
     use std::convert::Infallible;
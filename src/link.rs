@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io::{self, ErrorKind};
+use std::pin::pin;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::future::{select, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::Future;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::channel::Sender;
+use crate::codec::Codec;
+
+const DATA: u8 = 0;
+const CREDIT: u8 = 1;
+const REJECT: u8 = 2;
+
+/// Anything bigger is treated as a corrupt stream
+const MAX_FRAME: usize = 64 << 20;
+
+/// Times the remote may reject an item before a [`Dialer`] drops it
+const MAX_REJECTS: u32 = 3;
+
+/// What goes over a link, an encoded item or credit for more of them
+pub enum Frame {
+    Data(Bytes),
+    Credit(u32),
+    /// Acks as many items like [`Frame::Credit`], the one after them didn't decode
+    Reject(u32),
+}
+
+pub async fn write_frame(w: &mut (impl AsyncWrite + Unpin), frame: &Frame) -> io::Result<()> {
+    match frame {
+        Frame::Data(bytes) => {
+            w.write_u8(DATA).await?;
+            w.write_u32(bytes.len() as u32).await?;
+            w.write_all(bytes).await?;
+        },
+        Frame::Credit(n) => {
+            w.write_u8(CREDIT).await?;
+            w.write_u32(*n).await?;
+        },
+        Frame::Reject(n) => {
+            w.write_u8(REJECT).await?;
+            w.write_u32(*n).await?;
+        },
+    }
+
+    w.flush().await
+}
+
+/// Reads the next frame, `None` when the other side closed cleanly
+pub async fn read_frame(r: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Frame>> {
+    let tag = match r.read_u8().await {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    };
+
+    let n = r.read_u32().await?;
+
+    match tag {
+        DATA if n as usize <= MAX_FRAME => {
+            let mut bytes = vec![0; n as usize];
+            r.read_exact(&mut bytes).await?;
+
+            Ok(Some(Frame::Data(bytes.into())))
+        },
+        DATA => Err(io::Error::new(ErrorKind::InvalidData, format!("frame of {n} bytes"))),
+        CREDIT => Ok(Some(Frame::Credit(n))),
+        REJECT => Ok(Some(Frame::Reject(n))),
+        tag => Err(io::Error::new(ErrorKind::InvalidData, format!("unknown frame {tag}"))),
+    }
+}
+
+/// Serves one incoming link until it closes, granting `window` items of credit at a time
+///
+/// Credit is handed back once items made it into `tx`, so a slow consumer stalls the
+/// remote sender, and the sender takes it as an ack. Connection errors just end the link,
+/// and so does an item that doesn't decode, after logging it and telling the sender.
+/// A closed `tx` is returned.
+pub async fn serve<T, C: Codec<T>>(
+    mut r: impl AsyncRead + Unpin,
+    mut w: impl AsyncWrite + Unpin,
+    codec: &C,
+    tx: &Sender<T>,
+    window: u32,
+) -> anyhow::Result<()> {
+    if write_frame(&mut w, &Frame::Credit(window)).await.is_err() {
+        return Ok(());
+    }
+
+    let mut received = 0;
+
+    loop {
+        let bytes = match read_frame(&mut r).await {
+            Ok(Some(Frame::Data(bytes))) => bytes,
+            Ok(Some(Frame::Credit(_) | Frame::Reject(_))) | Ok(None) | Err(_) => return Ok(()),
+        };
+
+        let item = match codec.decode(&bytes) {
+            Ok(item) => item,
+            Err(e) => {
+                log::warn!("ending a link on an item that doesn't decode: {e:#}");
+                if write_frame(&mut w, &Frame::Reject(received)).await.is_ok() {
+                    // no more credit goes out, so the sender gets to read it once it's written what it could
+                    while let Ok(Some(_)) = read_frame(&mut r).await {}
+                }
+
+                return Ok(());
+            },
+        };
+
+        tx.send(item).await.map_err(|_| crate::channel::SendError(()))?;
+
+        received += 1;
+        if received >= window.div_ceil(2) {
+            if write_frame(&mut w, &Frame::Credit(received)).await.is_err() {
+                return Ok(());
+            }
+
+            received = 0;
+        }
+    }
+}
+
+/// Hands out incoming connections for [`serve_all`]
+pub trait Accept {
+    type Read: AsyncRead + Unpin + Send;
+    type Write: AsyncWrite + Unpin + Send;
+
+    fn accept(&self) -> impl Future<Output = io::Result<(Self::Read, Self::Write)>> + Send;
+}
+
+/// Serves every connection `listener` accepts at the same time, see [`serve`]
+pub async fn serve_all<T, C: Codec<T>>(listener: &impl Accept, codec: &C, tx: &Sender<T>, window: u32) -> anyhow::Result<Infallible> {
+    let mut links = FuturesUnordered::new();
+
+    loop {
+        let accepted = if links.is_empty() {
+            listener.accept().await
+        } else {
+            match select(pin!(listener.accept()), links.next()).await {
+                Either::Left((accepted, _)) => accepted,
+                Either::Right((res, _)) => {
+                    // a link only fails once `tx` is closed, which ends them all
+                    res.unwrap_or(Ok(()))?;
+                    continue;
+                },
+            }
+        };
+
+        let (r, w) = accepted?;
+        links.push(serve(r, w, codec, tx, window));
+    }
+}
+
+/// Opens the connection for a [`Dialer`]
+pub trait Connect {
+    type Read: AsyncRead + Unpin + Send;
+    type Write: AsyncWrite + Unpin + Send;
+
+    fn connect(&self) -> impl Future<Output = io::Result<(Self::Read, Self::Write)>> + Send;
+}
+
+/// The sending end of a link, connecting and reconnecting as needed
+///
+/// The credit the remote hands back after its first grant doubles as an ack for as many
+/// items, those not acked yet are kept and written again on the next connection. An item
+/// the remote keeps rejecting is dropped with an error logged, rather than sent forever.
+pub struct Dialer<K: Connect> {
+    connect: K,
+    conn: Option<(K::Read, K::Write, Link)>,
+    unacked: VecDeque<Bytes>,
+    /// how often the first unacked item was rejected
+    rejects: u32,
+    backoff: (Duration, Duration),
+}
+
+/// Where a connection is at
+#[derive(Default)]
+struct Link {
+    credit: u32,
+    /// whether the remote's first credit, its window, came in
+    granted: bool,
+    /// how many of the unacked items were written on this connection
+    written: usize,
+}
+
+impl<K: Connect + Sync> Dialer<K> {
+    pub fn new(connect: K) -> Self {
+        Self {
+            connect,
+            conn: None,
+            unacked: VecDeque::new(),
+            rejects: 0,
+            backoff: (Duration::from_millis(100), Duration::from_secs(10)),
+        }
+    }
+
+    /// Sends to a new destination from the next item on, along with the items the old
+    /// one didn't ack
+    pub fn redial(&mut self, connect: K) {
+        self.connect = connect;
+        self.conn = None;
+    }
+
+    pub fn backoff(&mut self, min: Duration, max: Duration) {
+        self.backoff = (min, max.max(min));
+    }
+
+    /// Sends `bytes` once the remote has credit for it, retrying on a new
+    /// connection for as long as it takes
+    pub async fn send(&mut self, bytes: Bytes) {
+        let mut delay = self.backoff.0;
+        self.unacked.push_back(bytes);
+
+        loop {
+            if self.conn.is_none() {
+                match self.connect.connect().await {
+                    Ok((r, w)) => self.conn = Some((r, w, Link::default())),
+                    Err(_) => {
+                        tokio::time::sleep(delay).await;
+                        delay = (delay * 2).min(self.backoff.1);
+                        continue;
+                    },
+                }
+            }
+
+            let (r, w, link) = self.conn.as_mut().unwrap();
+
+            match Self::send_on(r, w, link, &mut self.unacked, &mut self.rejects).await {
+                Ok(()) => return,
+                Err(_) => {
+                    // a remote that takes the connection but drops it right away is retried slowly too
+                    self.conn = None;
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.backoff.1);
+                },
+            }
+        }
+    }
+
+    /// Writes every unacked item this connection hasn't carried yet
+    async fn send_on(
+        r: &mut K::Read,
+        w: &mut K::Write,
+        link: &mut Link,
+        unacked: &mut VecDeque<Bytes>,
+        rejects: &mut u32,
+    ) -> io::Result<()> {
+        while link.written < unacked.len() {
+            while link.credit == 0 {
+                let (n, rejected) = match read_frame(r).await? {
+                    Some(Frame::Credit(n)) => (n, false),
+                    Some(Frame::Reject(n)) => (n, true),
+                    Some(Frame::Data(_)) => return Err(ErrorKind::InvalidData.into()),
+                    None => return Err(ErrorKind::UnexpectedEof.into()),
+                };
+
+                if link.granted {
+                    let acked = (n as usize).min(link.written);
+
+                    unacked.drain(..acked);
+                    link.written -= acked;
+
+                    if acked > 0 {
+                        *rejects = 0;
+                    }
+                }
+
+                if rejected {
+                    *rejects += 1;
+
+                    if *rejects >= MAX_REJECTS {
+                        log::error!("dropping an item the remote rejected {MAX_REJECTS} times");
+                        unacked.pop_front();
+                        *rejects = 0;
+                    }
+
+                    return Err(ErrorKind::InvalidData.into());
+                }
+
+                link.granted = true;
+                link.credit += n;
+            }
+
+            write_frame(w, &Frame::Data(unacked[link.written].clone())).await?;
+            link.written += 1;
+            link.credit -= 1;
+        }
+
+        Ok(())
+    }
+}
@@ -5,24 +5,38 @@ use std::{future::Future, pin::Pin, task};
 use futures::future::BoxFuture;
 use pin_project_lite::pin_project;
 
-use crate::channel::Sender;
+use crate::channel::Outlet;
 use crate::io::Poll;
 use crate::Error::*;
 use crate::util::as_static_mut;
 
 pub struct Poller<P: Poll> {
     poller: P,
-    sender: Sender<P::Item>,
+    outlet: Outlet<P::Item>,
 }
 
 impl<P: Poll> Poller<P> {
-    pub(crate) fn new(poller: P, tx: Sender<P::Item>) -> Self {
-        Self { poller, sender: tx }
+    pub(crate) fn new(poller: P, outlet: Outlet<P::Item>) -> Self {
+        Self { poller, outlet }
     }
 
     pub(crate) fn take_poller(self) -> P {
         self.poller
     }
+
+    pub(crate) fn take_parts(self) -> (P, Outlet<P::Item>) {
+        (self.poller, self.outlet)
+    }
+
+    #[cfg_attr(not(any(feature = "disk", feature = "per-key", feature = "process", feature = "schedule", feature = "stdio")), allow(dead_code))]
+    pub(crate) fn map_poller(self, f: impl FnOnce(P) -> P) -> Self {
+        Self { poller: f(self.poller), outlet: self.outlet }
+    }
+
+    #[cfg_attr(not(feature = "process"), allow(dead_code))]
+    pub(crate) fn poller_mut(&mut self) -> &mut P {
+        &mut self.poller
+    }
 }
 
 impl<P: Poll + 'static> IntoFuture for Poller<P> {
@@ -33,7 +47,7 @@ impl<P: Poll + 'static> IntoFuture for Poller<P> {
         Fut {
             fut: None,
             poller: self.poller,
-            sender: self.sender,
+            outlet: self.outlet,
         }
     }
 }
@@ -43,7 +57,7 @@ pin_project! {
         #[pin]
         fut: Option<BoxFuture<'static, anyhow::Result<Infallible>>>,
         poller: P,
-        sender: Sender<P::Item>
+        outlet: Outlet<P::Item>,
     }
 }
 
@@ -54,12 +68,17 @@ impl<P: Poll + 'static> Future for Fut<P> {
         let mut proj = self.project();
 
         if proj.fut.is_none() {
+            proj.outlet.validate().map_err(User)?;
+
             let poller = unsafe { as_static_mut(proj.poller) };
-            let fut = poller.poll(proj.sender.clone());
+            let fut = poller.poll(proj.outlet.sender());
 
             proj.fut.set(Some(Box::pin(fut)));
         }
 
-        proj.fut.as_pin_mut().unwrap().poll(cx).map_err(User)
+        let res = proj.fut.as_pin_mut().unwrap().poll(cx).map_err(crate::Error::from_poll);
+        let _ = proj.outlet.poll_relay(cx);
+
+        res
     }
 }
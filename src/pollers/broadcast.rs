@@ -1,68 +1,393 @@
 use std::convert::Infallible;
 use std::future::IntoFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::VecDeque;
+use std::sync::Arc;
 use std::{future::Future, pin::Pin, task, task::Poll::*};
 
 use futures::future::BoxFuture;
-use futures::Stream;
+use futures::stream::FuturesUnordered;
+use futures::{Stream, StreamExt};
 use pin_project_lite::pin_project;
 
-use crate::channel::{bounded, Receiver, SendError, Sender};
+use crate::budget::{Meter, Metering};
+use crate::channel::{bounded, unbounded, Channel, Receiver, SendError, Sender, TrySendError, WeakReceiver};
 use crate::io::Poll;
 use crate::Error::*;
 use crate::util::as_static_mut;
 
-pub struct Poller<P: Poll> {
-    poller: P,
-    senders: Vec<Sender<P::Item>>,
+/// What a broadcast does with an item when a subscriber's channel is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlowConsumer {
+    /// Wait until every subscriber has room
+    Block,
+    /// The lagging subscriber misses the new item
+    DropNewest,
+    /// The lagging subscriber misses its oldest queued item
+    DropOldest,
+    /// The lagging subscriber's channel is closed
+    #[default]
+    Disconnect,
+}
+
+/// Counts the items a subscriber missed because it fell behind, handed out with its receiver
+#[derive(Clone, Debug, Default)]
+pub struct Lag(Arc<AtomicUsize>);
+
+impl Lag {
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns the count and resets it, for checking what was missed since the last call
+    pub fn take(&self) -> usize {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+
+    fn add(&self) {
+        self.add_many(1);
+    }
+
+    fn add_many(&self, n: usize) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+struct Subscriber<T> {
+    sender: Sender<T>,
+    /// for [`SlowConsumer::DropOldest`] to make room in a full channel, weak so that the
+    /// channel closes once the subscriber drops its receiver
+    recver: WeakReceiver<T>,
+    lag: Lag,
+    /// replaces the [`SlowConsumer`] policy when the broadcast has a budget
+    meter: Option<Meter<T>>,
+}
+
+impl<T> Subscriber<T> {
+    fn is_gone(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+struct Join<T> {
+    sender: Sender<T>,
+    recver: WeakReceiver<T>,
+    lag: Lag,
+    replay: usize,
 }
 
-impl<P: Poll> Poller<P> {
-    pub(crate) fn new(poller: P, txs: Vec<Sender<P::Item>>) -> Self {
+/// Handle for subscribing to a running broadcast, see [`Poller::subscriptions`]
+///
+/// Dropping a receiver unsubscribes it, and once every handle is dropped the broadcast
+/// stops like a fixed one would when it runs out of subscribers.
+pub struct Subscriptions<T> {
+    joiner: Sender<Join<T>>,
+    channel: Channel,
+}
+
+impl<T> Clone for Subscriptions<T> {
+    fn clone(&self) -> Self {
         Self {
-            poller,
-            senders: txs,
+            joiner: self.joiner.clone(),
+            channel: self.channel,
+        }
+    }
+}
+
+impl<T> Subscriptions<T> {
+    pub fn subscribe(&self) -> (Receiver<T>, Lag) {
+        self.subscribe_with_last(0)
+    }
+
+    /// Subscribes, starting with up to `n` of the items kept by [`Poller::keep_last`]
+    ///
+    /// Only as many as fit in the subscriber's channel are replayed, the newest ones, the
+    /// rest count towards its [`Lag`].
+    pub fn subscribe_with_last(&self, n: usize) -> (Receiver<T>, Lag) {
+        let (sender, recver) = self.channel.create();
+        let lag = Lag::default();
+
+        // the receiver is closed right away if the broadcast already stopped
+        let _ = self.joiner.try_send(Join {
+            sender,
+            recver: recver.downgrade(),
+            lag: lag.clone(),
+            replay: n,
+        });
+
+        (recver, lag)
+    }
+}
+
+/// Hands every item to all subscribers, according to a [`SlowConsumer`] policy
+pub(crate) struct Fanout<T> {
+    subscribers: Vec<Subscriber<T>>,
+    policy: SlowConsumer,
+    blocked: FuturesUnordered<BoxFuture<'static, ()>>,
+    channel: Channel,
+    joiner: Option<Sender<Join<T>>>,
+    joining: Option<Pin<Box<Receiver<Join<T>>>>>,
+    history: VecDeque<T>,
+    keep: usize,
+    metering: Option<Metering<T>>,
+}
+
+impl<T: Clone + Send + 'static> Fanout<T> {
+    /// Subscribers get channels made from `channel`, which also picks the [`SlowConsumer`] policy
+    pub fn new(channel: Channel) -> Self {
+        let policy = match channel {
+            Channel::Unbounded | Channel::Bounded(_) => SlowConsumer::Block,
+            Channel::DropNewest(_) => SlowConsumer::DropNewest,
+            Channel::DropOldest(_) | Channel::Latest => SlowConsumer::DropOldest,
+        };
+
+        Self {
+            subscribers: Vec::new(),
+            policy,
+            blocked: FuturesUnordered::new(),
+            channel,
+            joiner: None,
+            joining: None,
+            history: VecDeque::new(),
+            keep: 0,
+            metering: None,
+        }
+    }
+
+    /// Every subscriber gets its own queue in front of a one item channel, sharing `metering`'s budget
+    pub fn metered(mut self, metering: Option<Metering<T>>) -> Self {
+        if metering.is_some() {
+            self.channel = Channel::bounded(1);
+        }
+
+        self.metering = metering;
+        self
+    }
+
+    /// Fails when subscribers were given an invalid [`Channel`]
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.channel.validate().map(drop)
+    }
+
+    pub fn subscribe(&mut self) -> (Receiver<T>, Lag) {
+        let (sender, recver) = self.channel.create();
+        let lag = Lag::default();
+
+        self.add(sender, recver.downgrade(), lag.clone());
+
+        (recver, lag)
+    }
+
+    fn add(&mut self, sender: Sender<T>, recver: WeakReceiver<T>, lag: Lag) {
+        self.subscribers.push(Subscriber {
+            sender,
+            lag,
+            meter: self.metering.clone().map(|metering| Meter::new(metering, recver.clone())),
+            recver,
+        });
+    }
+
+    pub fn set_policy(&mut self, policy: SlowConsumer) {
+        self.policy = policy;
+    }
+
+    pub fn keep_last(&mut self, n: usize) {
+        self.keep = n;
+    }
+
+    pub fn subscriptions(&mut self) -> Subscriptions<T> {
+        let joiner = self.joiner.get_or_insert_with(|| {
+            let (tx, rx) = unbounded();
+            self.joining = Some(Box::pin(rx));
+            tx
+        });
+
+        Subscriptions {
+            joiner: joiner.clone(),
+            channel: self.channel,
+        }
+    }
+
+    /// Called once the broadcast starts, so that only the user's handles keep it open
+    pub fn start(&mut self) {
+        self.joiner = None;
+    }
+
+    fn join(&mut self, join: Join<T>) {
+        let replay = join.replay.min(self.history.len());
+
+        self.add(join.sender, join.recver, join.lag);
+
+        let subscriber = self.subscribers.last_mut().unwrap();
+
+        // a budgeted subscriber queues everything, its budget decides what fits
+        let room = match subscriber.meter {
+            Some(_) => replay,
+            None => subscriber.sender.capacity().unwrap_or(replay).min(replay),
+        };
+
+        subscriber.lag.add_many(replay - room);
+
+        for item in self.history.iter().skip(self.history.len() - room) {
+            match subscriber.meter.as_mut() {
+                Some(meter) => subscriber.lag.add_many(meter.offer(item.clone())),
+                None => drop(subscriber.sender.try_send(item.clone())),
+            }
+        }
+    }
+
+    /// Ready once the previous item reached everyone it is going to reach
+    pub fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), crate::Error>> {
+        while let Some(joining) = self.joining.as_mut() {
+            match joining.as_mut().poll_next(cx) {
+                Ready(Some(join)) => self.join(join),
+                Ready(None) => self.joining = None,
+                Pending => break,
+            }
+        }
+
+        while futures::ready!(self.blocked.poll_next_unpin(cx)).is_some() {}
+
+        let mut waiting = false;
+        for subscriber in &mut self.subscribers {
+            if let Some(meter) = subscriber.meter.as_mut() {
+                waiting |= meter.poll_flush(&subscriber.sender, cx).is_pending();
+            }
+        }
+
+        if waiting {
+            return Pending;
+        }
+
+        self.subscribers.retain(|subscriber| !subscriber.is_gone());
+
+        if self.subscribers.is_empty() && self.joining.is_none() {
+            return Ready(Err(Internal(SendError(()).into())));
+        }
+
+        Ready(Ok(()))
+    }
+
+    pub fn send(&mut self, item: T) {
+        if self.keep > 0 {
+            if self.history.len() == self.keep {
+                self.history.pop_front();
+            }
+            self.history.push_back(item.clone());
+        }
+
+        for subscriber in &mut self.subscribers {
+            if let Some(meter) = subscriber.meter.as_mut() {
+                subscriber.lag.add_many(meter.offer(item.clone()));
+                continue;
+            }
+
+            let Err(TrySendError::Full(item)) = subscriber.sender.try_send(item.clone()) else {
+                continue;
+            };
+
+            match self.policy {
+                SlowConsumer::Block => {
+                    let sender = subscriber.sender.clone();
+
+                    // fails once the subscriber drops its receiver, which then removes it
+                    self.blocked.push(Box::pin(async move {
+                        let _ = sender.send(item).await;
+                    }));
+                },
+                SlowConsumer::DropNewest => subscriber.lag.add(),
+                SlowConsumer::DropOldest => {
+                    if let Some(recver) = subscriber.recver.upgrade() {
+                        let _ = recver.try_recv();
+                    }
+
+                    let _ = subscriber.sender.try_send(item);
+                    subscriber.lag.add();
+                },
+                SlowConsumer::Disconnect => {
+                    subscriber.sender.close();
+                    subscriber.lag.add();
+                },
+            }
         }
     }
 }
 
-impl<P: Poll + 'static> IntoFuture for Poller<P>
-where
-    P::Item: Clone,
-{
+/// Turns a polled item into what subscribers get
+pub(crate) type Wrap<P, T> = fn(<P as Poll>::Item) -> T;
+
+/// `T` is what subscribers get, either the item itself or an [`Arc`] around it
+pub struct Poller<P: Poll, T = <P as Poll>::Item> {
+    poller: P,
+    wrap: Wrap<P, T>,
+    fanout: Fanout<T>,
+}
+
+impl<P: Poll, T: Clone + Send + 'static> Poller<P, T> {
+    pub(crate) fn new(poller: P, wrap: Wrap<P, T>, fanout: Fanout<T>) -> Self {
+        Self { poller, wrap, fanout }
+    }
+
+    pub(crate) fn take_parts(self) -> (P, Wrap<P, T>, Fanout<T>) {
+        (self.poller, self.wrap, self.fanout)
+    }
+
+    /// What to do when a subscriber falls behind, by default picked from the broadcast's [`Channel`]
+    pub fn on_lag(mut self, policy: SlowConsumer) -> Self {
+        self.fanout.set_policy(policy);
+        self
+    }
+
+    /// Keeps the last `n` items around for [`Subscriptions::subscribe_with_last`]
+    pub fn keep_last(mut self, n: usize) -> Self {
+        self.fanout.keep_last(n);
+        self
+    }
+
+    /// A handle for adding subscribers while the broadcast runs
+    pub fn subscriptions(&mut self) -> Subscriptions<T> {
+        self.fanout.subscriptions()
+    }
+}
+
+impl<P: Poll + 'static, T: Clone + Send + 'static> IntoFuture for Poller<P, T> {
     type Output = Result<Infallible, crate::Error>;
-    type IntoFuture = Fut<P>;
+    type IntoFuture = Fut<P, T>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        self.fanout.start();
 
-    fn into_future(self) -> Self::IntoFuture {
         Fut {
             fut: None,
             recver: None,
             poller: self.poller,
-            senders: self.senders,
+            wrap: self.wrap,
+            fanout: self.fanout,
         }
     }
 }
 
 pin_project! {
-    pub struct Fut<P: Poll> {
+    pub struct Fut<P: Poll, T> {
         #[pin]
         fut: Option<BoxFuture<'static, anyhow::Result<Infallible>>>,
         #[pin]
         recver: Option<Receiver<P::Item>>,
         poller: P,
-        senders: Vec<Sender<P::Item>>
+        wrap: Wrap<P, T>,
+        fanout: Fanout<T>,
     }
 }
 
-impl<P: Poll + 'static> Future for Fut<P>
-where
-    P::Item: Clone,
-{
+impl<P: Poll + 'static, T: Clone + Send + 'static> Future for Fut<P, T> {
     type Output = Result<Infallible, crate::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
         let mut proj = self.project();
 
         if proj.fut.is_none() && proj.recver.is_none() {
+            proj.fanout.validate().map_err(User)?;
+
             let poller = unsafe { as_static_mut(proj.poller) };
             let (tx, rx) = bounded(1);
             let fut = poller.poll(tx);
@@ -72,20 +397,18 @@ where
         }
 
         let fut = proj.fut.as_pin_mut().unwrap();
-        let recver = proj.recver.as_pin_mut().unwrap();
+        let mut recver = proj.recver.as_pin_mut().unwrap();
 
         // the future is always pending after this point
-        let _ = fut.poll(cx).map_err(User)?;
+        let _ = fut.poll(cx).map_err(crate::Error::from_poll)?;
 
-        if let Some(item) = futures::ready!(recver.poll_next(cx)) {
-            proj.senders
-                .retain_mut(|sender| sender.try_send(item.clone()).is_ok());
+        loop {
+            futures::ready!(proj.fanout.poll_ready(cx))?;
 
-            if proj.senders.is_empty() {
-                return Ready(Err(Internal(SendError(()).into())));
+            match futures::ready!(recver.as_mut().poll_next(cx)) {
+                Some(item) => proj.fanout.send((proj.wrap)(item)),
+                None => return Pending,
             }
         }
-
-        Pending
     }
 }
@@ -1,15 +1,27 @@
-use crate::channel::{bounded, unbounded, Receiver};
-use crate::io::{Poll, State};
+use std::convert::identity;
+use std::sync::Arc;
+
+use crate::budget::{Budget, ByteSize, Metering, OverBudget};
+use crate::channel::{Channel, Outlet, Receiver};
+use crate::io::{AsyncState, Poll};
+use crate::state::IntoStateReceiver;
 
 pub use basic::Poller;
-pub use broadcast::Poller as BroadcastPoller;
+pub use broadcast::{Lag, Poller as BroadcastPoller, SlowConsumer, Subscriptions};
 pub use stateful::Poller as StatefulPoller;
+pub use stateful_broadcast::Poller as StatefulBroadcastPoller;
+
+use broadcast::Fanout;
+
+/// A broadcast made with [`UpgradePoller::broadcast_shared`]
+pub type SharedBroadcastPoller<P> = BroadcastPoller<P, Arc<<P as Poll>::Item>>;
 
 mod basic;
 
 mod broadcast;
 
 mod stateful;
+mod stateful_broadcast;
 
 pub trait IntoPoller<P: Poll> {
     fn into_poller(self) -> P;
@@ -22,40 +34,145 @@ impl<P: Poll> IntoPoller<P> for P {
 }
 
 pub trait UpgradePoller<P: Poll> {
+    /// Replaces the poller's output channel
+    fn channel(self, channel: Channel) -> (Poller<P>, Receiver<P::Item>);
+
+    /// Replaces the poller's output channel with a queue whose items hold on to `budget`,
+    /// broadcasts made from it give every subscriber such a queue
+    fn budget(self, budget: Budget, policy: OverBudget) -> (Poller<P>, Receiver<P::Item>)
+    where
+        P::Item: ByteSize + 'static;
+
     fn broadcast<const C: usize>(self) -> (BroadcastPoller<P>, [Receiver<P::Item>; C])
     where
-        P::Item: Clone;
+        P::Item: Clone + 'static;
 
-    fn with_state<S>(self, state_rx: Receiver<S>) -> (StatefulPoller<S, P>, Receiver<P::Item>)
+    /// Like [`UpgradePoller::broadcast`], with every subscriber getting a `channel` and
+    /// a [`Lag`] counting the items it missed
+    #[allow(clippy::type_complexity)]
+    fn broadcast_with<const C: usize>(self, channel: Channel) -> (BroadcastPoller<P>, [(Receiver<P::Item>, Lag); C])
     where
-        P: State<S>;
+        P::Item: Clone + 'static;
+
+    /// A broadcast that starts without subscribers, they are added through the returned handle
+    fn broadcast_dynamic(self, channel: Channel) -> (BroadcastPoller<P>, Subscriptions<P::Item>)
+    where
+        P::Item: Clone + 'static;
+
+    /// Like [`UpgradePoller::broadcast_with`], but every item is put in an [`Arc`] once
+    /// and shared between subscribers instead of being cloned for each of them
+    #[allow(clippy::type_complexity)]
+    fn broadcast_shared<const C: usize>(
+        self,
+        channel: Channel,
+    ) -> (SharedBroadcastPoller<P>, [(Receiver<Arc<P::Item>>, Lag); C])
+    where
+        P::Item: Sync + 'static;
+
+    fn with_state<S>(self, state_rx: impl IntoStateReceiver<S>) -> (StatefulPoller<S, P>, Receiver<P::Item>)
+    where
+        P: AsyncState<S>,
+        S: 'static;
 }
 
 impl<P: Poll> UpgradePoller<P> for (Poller<P>, Receiver<P::Item>) {
-    fn broadcast<const C: usize>(self) -> (BroadcastPoller<P>, [Receiver<P::Item>; C])
+    fn channel(self, channel: Channel) -> (Poller<P>, Receiver<P::Item>) {
+        let p = self.0.take_poller();
+
+        let (outlet, rx) = Outlet::new(channel);
+
+        (Poller::new(p, outlet), rx)
+    }
+
+    fn budget(self, budget: Budget, policy: OverBudget) -> (Poller<P>, Receiver<P::Item>)
     where
-        P::Item: Clone,
+        P::Item: ByteSize + 'static,
     {
         let p = self.0.take_poller();
 
-        let mut txs = Vec::with_capacity(C);
-        let rxs = core::array::from_fn(|_| {
-            let (tx, rx) = bounded(10);
-            txs.push(tx);
-            rx
-        });
+        let (outlet, rx) = Outlet::metered(Metering::new(budget, policy));
 
-        (BroadcastPoller::new(p, txs), rxs)
+        (Poller::new(p, outlet), rx)
     }
 
-    fn with_state<S>(self, state_rx: Receiver<S>) -> (StatefulPoller<S, P>, Receiver<P::Item>)
+    fn broadcast<const C: usize>(self) -> (BroadcastPoller<P>, [Receiver<P::Item>; C])
     where
-        P: State<S>,
+        P::Item: Clone + 'static,
     {
-        let p = self.0.take_poller();
+        let (poller, rxs) = self.broadcast_with(Channel::bounded(10));
+
+        // a disconnected subscriber sees its channel close, it has no use for the lag
+        (poller.on_lag(SlowConsumer::Disconnect), rxs.map(|(rx, _)| rx))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn broadcast_with<const C: usize>(self, channel: Channel) -> (BroadcastPoller<P>, [(Receiver<P::Item>, Lag); C])
+    where
+        P::Item: Clone + 'static,
+    {
+        let (p, outlet) = self.0.take_parts();
+
+        let mut fanout = Fanout::new(channel).metered(outlet.metering());
+        let rxs = core::array::from_fn(|_| fanout.subscribe());
 
-        let (tx, rx) = unbounded();
+        (BroadcastPoller::new(p, identity, fanout), rxs)
+    }
+
+    fn broadcast_dynamic(self, channel: Channel) -> (BroadcastPoller<P>, Subscriptions<P::Item>)
+    where
+        P::Item: Clone + 'static,
+    {
+        let (p, outlet) = self.0.take_parts();
+
+        let mut fanout = Fanout::new(channel).metered(outlet.metering());
+        let subscriptions = fanout.subscriptions();
+
+        (BroadcastPoller::new(p, identity, fanout), subscriptions)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn broadcast_shared<const C: usize>(
+        self,
+        channel: Channel,
+    ) -> (SharedBroadcastPoller<P>, [(Receiver<Arc<P::Item>>, Lag); C])
+    where
+        P::Item: Sync + 'static,
+    {
+        let (p, outlet) = self.0.take_parts();
+
+        let mut fanout = Fanout::new(channel).metered(outlet.metering().map(Metering::shared));
+        let rxs = core::array::from_fn(|_| fanout.subscribe());
+
+        (BroadcastPoller::new(p, Arc::new, fanout), rxs)
+    }
+
+    fn with_state<S>(self, state_rx: impl IntoStateReceiver<S>) -> (StatefulPoller<S, P>, Receiver<P::Item>)
+    where
+        P: AsyncState<S>,
+        S: 'static,
+    {
+        let (p, outlet) = self.0.take_parts();
+
+        (StatefulPoller::new(p, outlet, state_rx), self.1)
+    }
+}
+
+pub trait UpgradeBroadcastPoller<P: Poll, T, R> {
+    fn with_state<S>(self, state_rx: impl IntoStateReceiver<S>) -> (StatefulBroadcastPoller<S, P, T>, R)
+    where
+        P: AsyncState<S>,
+        S: 'static;
+}
+
+/// `R` is whatever the broadcast handed out for subscribing, receivers or [`Subscriptions`]
+impl<P: Poll, T: Clone + Send + 'static, R> UpgradeBroadcastPoller<P, T, R> for (BroadcastPoller<P, T>, R) {
+    fn with_state<S>(self, state_rx: impl IntoStateReceiver<S>) -> (StatefulBroadcastPoller<S, P, T>, R)
+    where
+        P: AsyncState<S>,
+        S: 'static,
+    {
+        let (p, wrap, fanout) = self.0.take_parts();
 
-        (StatefulPoller::new(p, tx, state_rx), rx)
+        (StatefulBroadcastPoller::new(p, wrap, fanout, state_rx), self.1)
     }
 }
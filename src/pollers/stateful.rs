@@ -3,80 +3,105 @@ use std::future::IntoFuture;
 use std::{future::Future, pin::Pin, task, task::Poll::*};
 
 use futures::future::BoxFuture;
-use futures::Stream;
 use pin_project_lite::pin_project;
 
-use crate::channel::{Receiver, Sender, RecvError};
+use crate::channel::{Outlet, RecvError};
 use crate::Error::*;
-use crate::io::{Poll, State};
+use crate::io::{AsyncState, Poll, Rejected};
+use crate::state::{IntoStateReceiver, StateReceiver};
+use crate::update::Updater;
 use crate::util::as_static_mut;
 
-pub struct Poller<S, P: Poll + State<S>> {
+pub struct Poller<S, P: Poll + AsyncState<S>> {
     poller: P,
-    recver: Receiver<S>,
-    sender: Sender<P::Item>,
+    updater: Updater<S>,
+    recver: StateReceiver<S>,
+    outlet: Outlet<P::Item>,
 }
 
-impl<S, P: Poll + State<S>> Poller<S, P> {
-    pub(super) fn new(poller: P, tx: Sender<P::Item>, srx: Receiver<S>) -> Self {
+impl<S: 'static, P: Poll + AsyncState<S>> Poller<S, P> {
+    pub(super) fn new(poller: P, outlet: Outlet<P::Item>, srx: impl IntoStateReceiver<S>) -> Self {
         Self {
             poller,
-            recver: srx,
-            sender: tx,
+            updater: Updater::new(),
+            recver: srx.into_state_receiver(),
+            outlet,
         }
     }
+
+    /// What to do when the poller rejects a state, [`Rejected::Fail`] by default
+    pub fn on_reject(mut self, policy: Rejected) -> Self
+    where
+        S: Clone,
+    {
+        self.updater.set_policy(policy);
+        self
+    }
 }
 
-impl<S, P: Poll + State<S> + 'static> IntoFuture for Poller<S, P> {
+impl<S: 'static, P: Poll + AsyncState<S> + 'static> IntoFuture for Poller<S, P> {
     type Output = Result<Infallible, crate::Error>;
     type IntoFuture = Fut<S, P>;
 
     fn into_future(self) -> Self::IntoFuture {
         Fut {
             fut: None,
+            updater: self.updater,
             recver: self.recver,
             poller: self.poller,
-            sender: self.sender,
+            outlet: self.outlet,
         }
     }
 }
 
 pin_project! {
-    pub struct Fut<S, P: Poll>
+    pub struct Fut<S, P>
     where
-        P: State<S>,
+        P: Poll,
+        P: AsyncState<S>,
     {
         #[pin]
         fut: Option<BoxFuture<'static, anyhow::Result<Infallible>>>,
+        updater: Updater<S>,
         #[pin]
-        recver: Receiver<S>,
+        recver: StateReceiver<S>,
         poller: P,
-        sender: Sender<P::Item>
+        outlet: Outlet<P::Item>,
     }
 }
 
-impl<S, P: Poll + State<S> + 'static> Future for Fut<S, P> {
+impl<S: 'static, P: Poll + AsyncState<S> + 'static> Future for Fut<S, P> {
     type Output = Result<Infallible, crate::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
         let mut proj = self.project();
 
-        match proj.recver.poll_next(cx) {
-            Ready(Some(state)) => {
-                proj.fut.set(None);
-                proj.poller.update(state);
-            },
-            Ready(None) => return Ready(Err(Internal(RecvError.into()))),
-            _ => (),
+        // updates are applied in order, and never while the poll future is alive
+        loop {
+            futures::ready!(proj.updater.poll_update(proj.poller, cx))?;
+
+            match proj.recver.as_mut().poll_state(cx) {
+                Ready(Some((state, ack))) => {
+                    proj.fut.set(None);
+                    proj.updater.start(proj.poller, state, ack);
+                },
+                Ready(None) => return Ready(Err(Internal(RecvError.into()))),
+                Pending => break,
+            }
         }
 
         if proj.fut.is_none() {
+            proj.outlet.validate().map_err(User)?;
+
             let poller = unsafe { as_static_mut(proj.poller) };
-            let fut = poller.poll(proj.sender.clone());
+            let fut = poller.poll(proj.outlet.sender());
 
             proj.fut.set(Some(Box::pin(fut)));
         }
 
-        proj.fut.as_pin_mut().unwrap().poll(cx).map_err(User)
+        let res = proj.fut.as_pin_mut().unwrap().poll(cx).map_err(crate::Error::from_poll);
+        let _ = proj.outlet.poll_relay(cx);
+
+        res
     }
 }
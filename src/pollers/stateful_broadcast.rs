@@ -0,0 +1,166 @@
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::{future::Future, pin::Pin, task, task::Poll::*};
+
+use futures::future::BoxFuture;
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::channel::{bounded, Receiver, RecvError};
+use crate::io::{AsyncState, Poll, Rejected};
+use crate::state::{IntoStateReceiver, StateReceiver};
+use crate::update::Updater;
+use crate::Error::*;
+use crate::util::as_static_mut;
+
+use super::broadcast::{Fanout, Wrap, SlowConsumer, Subscriptions};
+
+pub struct Poller<S, P: Poll + AsyncState<S>, T = <P as Poll>::Item> {
+    poller: P,
+    updater: Updater<S>,
+    recver: StateReceiver<S>,
+    wrap: Wrap<P, T>,
+    fanout: Fanout<T>,
+}
+
+impl<S: 'static, P: Poll + AsyncState<S>, T: Clone + Send + 'static> Poller<S, P, T> {
+    pub(super) fn new(
+        poller: P,
+        wrap: Wrap<P, T>,
+        fanout: Fanout<T>,
+        srx: impl IntoStateReceiver<S>,
+    ) -> Self {
+        Self {
+            poller,
+            updater: Updater::new(),
+            recver: srx.into_state_receiver(),
+            wrap,
+            fanout,
+        }
+    }
+
+    /// What to do when a subscriber falls behind, by default picked from the broadcast's [`Channel`](crate::channel::Channel)
+    pub fn on_lag(mut self, policy: SlowConsumer) -> Self {
+        self.fanout.set_policy(policy);
+        self
+    }
+
+    /// Keeps the last `n` items around for [`Subscriptions::subscribe_with_last`]
+    pub fn keep_last(mut self, n: usize) -> Self {
+        self.fanout.keep_last(n);
+        self
+    }
+
+    /// A handle for adding subscribers while the broadcast runs
+    pub fn subscriptions(&mut self) -> Subscriptions<T> {
+        self.fanout.subscriptions()
+    }
+
+    /// What to do when the poller rejects a state, [`Rejected::Fail`] by default
+    pub fn on_reject(mut self, policy: Rejected) -> Self
+    where
+        S: Clone,
+    {
+        self.updater.set_policy(policy);
+        self
+    }
+}
+
+impl<S: 'static, P: Poll + AsyncState<S> + 'static, T: Clone + Send + 'static> IntoFuture for Poller<S, P, T> {
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = Fut<S, P, T>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        self.fanout.start();
+
+        Fut {
+            fut: None,
+            recver: None,
+            updater: self.updater,
+            state_recver: self.recver,
+            poller: self.poller,
+            wrap: self.wrap,
+            fanout: self.fanout,
+        }
+    }
+}
+
+pin_project! {
+    pub struct Fut<S, P, T>
+    where
+        P: Poll,
+        P: AsyncState<S>,
+    {
+        #[pin]
+        fut: Option<BoxFuture<'static, anyhow::Result<Infallible>>>,
+        #[pin]
+        recver: Option<Receiver<P::Item>>,
+        updater: Updater<S>,
+        #[pin]
+        state_recver: StateReceiver<S>,
+        poller: P,
+        wrap: Wrap<P, T>,
+        fanout: Fanout<T>,
+    }
+}
+
+impl<S: 'static, P: Poll + AsyncState<S> + 'static, T: Clone + Send + 'static> Future for Fut<S, P, T> {
+    type Output = Result<Infallible, crate::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
+        let mut proj = self.project();
+
+        loop {
+            futures::ready!(proj.updater.poll_update(proj.poller, cx))?;
+
+            match proj.state_recver.as_mut().poll_state(cx) {
+                Ready(Some((state, ack))) => {
+                    proj.fut.set(None);
+                    proj.updater.start(proj.poller, state, ack);
+                },
+                Ready(None) => return Ready(Err(Internal(RecvError.into()))),
+                Pending => break,
+            }
+        }
+
+        // items the old poll future already produced still go out, it dropped its sender
+        if let (true, Some(mut recver)) = (proj.fut.is_none(), proj.recver.as_mut().as_pin_mut()) {
+            loop {
+                futures::ready!(proj.fanout.poll_ready(cx))?;
+
+                match futures::ready!(recver.as_mut().poll_next(cx)) {
+                    Some(item) => proj.fanout.send((proj.wrap)(item)),
+                    None => break,
+                }
+            }
+
+            proj.recver.set(None);
+        }
+
+        if proj.fut.is_none() {
+            proj.fanout.validate().map_err(User)?;
+
+            let poller = unsafe { as_static_mut(proj.poller) };
+            let (tx, rx) = bounded(1);
+            let fut = poller.poll(tx);
+
+            proj.fut.set(Some(Box::pin(fut)));
+            proj.recver.set(Some(rx));
+        }
+
+        let fut = proj.fut.as_pin_mut().unwrap();
+        let mut recver = proj.recver.as_pin_mut().unwrap();
+
+        // the future is always pending after this point
+        let _ = fut.poll(cx).map_err(crate::Error::from_poll)?;
+
+        loop {
+            futures::ready!(proj.fanout.poll_ready(cx))?;
+
+            match futures::ready!(recver.as_mut().poll_next(cx)) {
+                Some(item) => proj.fanout.send((proj.wrap)(item)),
+                None => return Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::pin::pin;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use futures::future::{pending, select, Either};
+use futures::stream::{self, Stream, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::ChildStdin;
+
+use crate::channel::{bounded, Channel, Outlet, Receiver, SendError, Sender};
+use crate::io::{Poll, Push};
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// A line the child printed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Line {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// The child exited unsuccessfully, the error a [`Command`] stage fails with
+#[derive(Debug)]
+pub struct Exited {
+    pub program: String,
+    pub status: ExitStatus,
+}
+
+impl fmt::Display for Exited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} exited with {}", self.program, self.status)
+    }
+}
+
+impl std::error::Error for Exited {}
+
+/// When a [`Command`] runs its child again
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Restart {
+    /// a clean exit ends the stage like the end of input, a failure fails it with [`Exited`]
+    #[default]
+    Never,
+    /// a clean exit ends the stage, failures are restarted
+    OnFailure,
+    /// the child is kept running no matter how it exits
+    Always,
+}
+
+/// Runs a child process and reads the lines it prints, made by [`poll_command`]
+///
+/// The child is killed when the stage is dropped. Output that isn't UTF-8 is replaced
+/// lossily rather than failing the stage, legacy scripts print all sorts.
+pub struct Command {
+    command: tokio::process::Command,
+    restart: Restart,
+    delay: Duration,
+    stdin: Option<Receiver<String>>,
+}
+
+/// Runs `command` with its stdout and stderr piped into the output channel
+pub fn poll_command(command: impl Into<tokio::process::Command>) -> (Poller<Command>, Receiver<Line>) {
+    let (outlet, rx) = Outlet::new(Channel::bounded(1));
+
+    let mut command = command.into();
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+
+    (Poller::new(Command { command, restart: Restart::Never, delay: Duration::from_secs(1), stdin: None }, outlet), rx)
+}
+
+impl Poller<Command> {
+    pub fn restart(self, restart: Restart) -> Self {
+        self.map_poller(|command| Command { restart, ..command })
+    }
+
+    /// How long to wait before restarting, 1s by default
+    pub fn restart_delay(self, delay: Duration) -> Self {
+        self.map_poller(|command| Command { delay, ..command })
+    }
+
+    /// A pusher writing lines to the child's stdin, across restarts
+    ///
+    /// Dropping it closes the child's stdin. Lines still in the pipe when a child dies
+    /// are lost with it.
+    pub fn stdin(&mut self) -> CommandStdin {
+        let (tx, rx) = bounded(1);
+        let command = self.poller_mut();
+
+        command.command.stdin(Stdio::piped());
+        command.stdin = Some(rx);
+
+        CommandStdin { sender: tx }
+    }
+}
+
+impl Command {
+    fn program(&self) -> String {
+        self.command.as_std().get_program().to_string_lossy().into_owned()
+    }
+
+    /// Runs the child once, until it exited and all its output is sent
+    async fn run(&mut self, tx: &Sender<Line>) -> Result<ExitStatus, crate::Error> {
+        let mut child = self.command.spawn().with_context(|| format!("spawning {}", self.program())).map_err(User)?;
+
+        let stdout = lines(child.stdout.take().unwrap(), Line::Stdout);
+        let stderr = lines(child.stderr.take().unwrap(), Line::Stderr);
+
+        let output = async {
+            let mut lines = pin!(stream::select(stdout, stderr));
+
+            while let Some(line) = lines.next().await {
+                tx.send(line).await.map_err(|_| Internal(SendError(()).into()))?;
+            }
+
+            Ok(())
+        };
+
+        if let Either::Left((res, _)) = select(pin!(output), pin!(feed(child.stdin.take(), self.stdin.as_ref()))).await {
+            res?;
+        }
+
+        child.wait().await.context("waiting for child").map_err(User)
+    }
+}
+
+fn lines<R: AsyncRead + Unpin>(reader: R, line: fn(String) -> Line) -> impl Stream<Item = Line> {
+    stream::unfold(BufReader::new(reader), move |mut reader| async move {
+        let mut buf = Vec::new();
+
+        // a read error means the pipe is gone just like the end of it does
+        match reader.read_until(b'\n', &mut buf).await {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                if buf.ends_with(b"\n") {
+                    buf.pop();
+                }
+
+                if buf.ends_with(b"\r") {
+                    buf.pop();
+                }
+
+                Some((line(String::from_utf8_lossy(&buf).into_owned()), reader))
+            },
+        }
+    })
+}
+
+/// Writes items to the child until its stdin or the items run out, never finishing
+async fn feed(stdin: Option<ChildStdin>, items: Option<&Receiver<String>>) {
+    if let (Some(mut stdin), Some(items)) = (stdin, items) {
+        while let Ok(item) = items.recv().await {
+            let line = [item.as_bytes(), b"\n"].concat();
+
+            if stdin.write_all(&line).await.is_err() || stdin.flush().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    pending().await
+}
+
+impl Poll for Command {
+    type Item = Line;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            let status = self.run(&tx).await?;
+
+            match self.restart {
+                Restart::Always => {},
+                Restart::OnFailure if !status.success() => {},
+                _ if status.success() => return Err(Internal(anyhow!("{} finished", self.program())).into()),
+                _ => return Err(Exited { program: self.program(), status }.into()),
+            }
+
+            tokio::time::sleep(self.delay).await;
+        }
+    }
+}
+
+/// Writes lines to the stdin of a [`Command`]'s child, from `stdin` on its [`Poller`]
+pub struct CommandStdin {
+    sender: Sender<String>,
+}
+
+impl Push<String> for CommandStdin {
+    async fn push(&mut self, item: String) -> anyhow::Result<()> {
+        self.sender.send(item).await.context("command stage is gone")?;
+        Ok(())
+    }
+}
@@ -7,6 +7,10 @@ use pin_project_lite::pin_project;
 
 use crate::channel::{Receiver, RecvError};
 use crate::Error::*;
+use crate::io::{Push, State};
+use crate::state::IntoStateReceiver;
+
+use super::StatefulPusher;
 
 pub struct Pusher<T, F: Fn(T)> {
     recver: Receiver<T>,
@@ -17,6 +21,22 @@ impl<T, F: Fn(T)> Pusher<T, F> {
     pub fn new(func: F, rx: Receiver<T>) -> Self {
         Self { recver: rx, func }
     }
+
+    /// Makes the function itself the state, every update from `state_rx` replaces it
+    ///
+    /// Closures all have their own type, so send boxed ones or function pointers. For a
+    /// function that sees some other state along with the items, see [`To::to_state_fn`](super::To::to_state_fn).
+    #[allow(clippy::type_complexity)]
+    pub fn with_state(self, state_rx: impl IntoStateReceiver<F>) -> StatefulPusher<T, F, StateFn<F, fn(&mut F, T)>>
+    where
+        F: 'static,
+    {
+        StatefulPusher::new(StateFn::new(self.func, call as fn(&mut F, T)), self.recver, state_rx)
+    }
+}
+
+fn call<T, F: Fn(T)>(func: &mut F, item: T) {
+    func(item)
 }
 
 impl<T, F: Fn(T)> IntoFuture for Pusher<T, F> {
@@ -54,3 +74,29 @@ impl<T, F: Fn(T)> Future for Fut<T, F> {
         }
     }
 }
+
+/// A function that also sees the latest state, turned into a [`Push`] so
+/// that it can be upgraded with `with_state` like any other pusher
+pub struct StateFn<S, F> {
+    state: S,
+    func: F,
+}
+
+impl<S, F> StateFn<S, F> {
+    pub fn new(state: S, func: F) -> Self {
+        Self { state, func }
+    }
+}
+
+impl<S, F> State<S> for StateFn<S, F> {
+    fn update(&mut self, state: S) {
+        self.state = state;
+    }
+}
+
+impl<T, S, F: Fn(&mut S, T)> Push<T> for StateFn<S, F> {
+    fn push(&mut self, item: T) -> impl Future<Output = anyhow::Result<()>> + Send + '_ {
+        (self.func)(&mut self.state, item);
+        std::future::ready(Ok(()))
+    }
+}
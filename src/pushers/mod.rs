@@ -1,8 +1,10 @@
 use crate::channel::Receiver;
 use crate::io::Push;
+use crate::state::IntoStateReceiver;
 
 pub use basic::Pusher;
 pub use function::Pusher as FunctionPusher;
+pub use function::StateFn;
 pub use stateful::Pusher as StatefulPusher;
 
 mod basic;
@@ -24,6 +26,7 @@ pub struct EmptyPusher;
 pub trait To<T> {
     fn to<P: Push<T>>(self, p: P) -> Pusher<T, P>;
     fn to_fn<F: Fn(T)>(self, p: F) -> FunctionPusher<T, F>;
+    fn to_state_fn<S, F: Fn(&mut S, T)>(self, state: S, p: F) -> Pusher<T, StateFn<S, F>>;
 }
 
 impl<T> To<T> for (EmptyPusher, Receiver<T>) {
@@ -33,14 +36,17 @@ impl<T> To<T> for (EmptyPusher, Receiver<T>) {
     fn to_fn<F: Fn(T)>(self, p: F) -> FunctionPusher<T, F> {
         FunctionPusher::new(p, self.1)
     }
+    fn to_state_fn<S, F: Fn(&mut S, T)>(self, state: S, p: F) -> Pusher<T, StateFn<S, F>> {
+        Pusher::new(StateFn::new(state, p), self.1)
+    }
 }
 
 pub trait UpgradePusher<T, P: Push<T>> {
-    fn with_state<S>(self, state_rx: Receiver<S>) -> StatefulPusher<T, S, P>;
+    fn with_state<S: 'static>(self, state_rx: impl IntoStateReceiver<S>) -> StatefulPusher<T, S, P>;
 }
 
 impl<T, P: Push<T>> UpgradePusher<T, P> for Pusher<T, P> {
-    fn with_state<S>(self, state_rx: Receiver<S>) -> StatefulPusher<T, S, P> {
+    fn with_state<S: 'static>(self, state_rx: impl IntoStateReceiver<S>) -> StatefulPusher<T, S, P> {
         let (tx, pusher) = self.take_parts();
         StatefulPusher::new(pusher, tx, state_rx)
     }
@@ -8,28 +8,41 @@ use pin_project_lite::pin_project;
 
 use crate::channel::{Receiver, RecvError};
 use crate::Error::*;
-use crate::io::{Push, State};
+use crate::io::{AsyncState, Push, Rejected};
+use crate::state::{IntoStateReceiver, StateReceiver};
+use crate::update::Updater;
 use crate::util::as_static_mut;
 
 pub struct Pusher<T, S, P> {
     recver: Receiver<T>,
-    state_recver: Receiver<S>,
+    state_recver: StateReceiver<S>,
+    updater: Updater<S>,
     pusher: P,
 }
 
-impl<T, S, P> Pusher<T, S, P> {
-    pub fn new(pusher: P, rx: Receiver<T>, srx: Receiver<S>) -> Self {
-        Self { recver: rx, state_recver: srx, pusher }
+impl<T, S: 'static, P> Pusher<T, S, P> {
+    pub fn new(pusher: P, rx: Receiver<T>, srx: impl IntoStateReceiver<S>) -> Self {
+        Self { recver: rx, state_recver: srx.into_state_receiver(), updater: Updater::new(), pusher }
+    }
+
+    /// What to do when the pusher rejects a state, [`Rejected::Fail`] by default
+    pub fn on_reject(mut self, policy: Rejected) -> Self
+    where
+        S: Clone,
+    {
+        self.updater.set_policy(policy);
+        self
     }
 }
 
-impl<T, S, P: Push<T> + State<S> + 'static> IntoFuture for Pusher<T, S, P> {
+impl<T, S: 'static, P: Push<T> + AsyncState<S> + 'static> IntoFuture for Pusher<T, S, P> {
     type Output = Result<Infallible, crate::Error>;
     type IntoFuture = Fut<T, S, P>;
 
     fn into_future(self) -> Self::IntoFuture {
         Fut {
             fut: None,
+            updater: self.updater,
             recver: self.recver,
             state_recver: self.state_recver,
             pusher: self.pusher,
@@ -38,21 +51,23 @@ impl<T, S, P: Push<T> + State<S> + 'static> IntoFuture for Pusher<T, S, P> {
 }
 
 pin_project! {
-    pub struct Fut<T, S, P: Push<T>>
+    pub struct Fut<T, S, P>
     where
-        P: State<S>,
+        P: Push<T>,
+        P: AsyncState<S>,
     {
         #[pin]
         fut: Option<BoxFuture<'static, anyhow::Result<()>>>,
+        updater: Updater<S>,
         #[pin]
         recver: Receiver<T>,
         #[pin]
-        state_recver: Receiver<S>,
+        state_recver: StateReceiver<S>,
         pusher: P,
     }
 }
 
-impl<T, S, P: Push<T> + State<S> + 'static> Future for Fut<T, S, P> {
+impl<T, S: 'static, P: Push<T> + AsyncState<S> + 'static> Future for Fut<T, S, P> {
     type Output = Result<Infallible, crate::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> task::Poll<Self::Output> {
@@ -64,9 +79,11 @@ impl<T, S, P: Push<T> + State<S> + 'static> Future for Fut<T, S, P> {
         }
 
         loop {
-            match proj.state_recver.as_mut().poll_next(cx) {
-                Ready(Some(state)) => {
-                    proj.pusher.update(state);
+            futures::ready!(proj.updater.poll_update(proj.pusher, cx))?;
+
+            match proj.state_recver.as_mut().poll_state(cx) {
+                Ready(Some((state, ack))) => {
+                    proj.updater.start(proj.pusher, state, ack);
                 }
                 Ready(None) => return Ready(Err(Internal(RecvError.into()))),
                 Pending => break,
@@ -0,0 +1,121 @@
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufReader, BufWriter};
+
+use crate::channel::{self, Channel, Outlet, Receiver, SendError};
+use crate::disk;
+use crate::io::Poll;
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// Writes every item passing through to a file, along with when it passed
+pub struct Record<T> {
+    recver: Receiver<T>,
+    path: PathBuf,
+}
+
+/// Records everything from `rx` to `path`, the returned receiver gets the items unchanged
+///
+/// An existing file at `path` is replaced. Play it back with [`poll_replay`].
+pub fn record<T: Serialize + Send>(rx: Receiver<T>, path: impl Into<PathBuf>) -> (Poller<Record<T>>, Receiver<T>) {
+    let (outlet, out) = Outlet::new(Channel::bounded(1));
+
+    (Poller::new(Record { recver: rx, path: path.into() }, outlet), out)
+}
+
+impl<T: Serialize + Send> Poll for Record<T> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut w = BufWriter::new(File::create(&self.path).await?);
+        let start = Instant::now();
+
+        loop {
+            let item = self.recver.recv().await.map_err(|e| Internal(e.into()))?;
+            let at = start.elapsed().as_micros() as u64;
+
+            let bytes = disk::encode(&(at, &item))?;
+            w.write_all(&bytes).await?;
+
+            // a burst is written out together, once there's nothing more waiting
+            if self.recver.is_empty() {
+                w.flush().await?;
+            }
+
+            tx.send(item).await.map_err(|_| Internal(SendError(()).into()))?;
+        }
+    }
+}
+
+/// How fast [`poll_replay`] plays a recording back
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Speed {
+    /// With the gaps between items as recorded
+    #[default]
+    Original,
+    /// Gaps divided by the factor, `Times(2.0)` plays twice as fast, it has to be finite and above zero
+    Times(f64),
+    /// As fast as the consumer takes them
+    Max,
+}
+
+/// Plays back a file written by [`record`]
+pub struct Replay<T> {
+    path: PathBuf,
+    speed: Speed,
+    _item: std::marker::PhantomData<fn() -> T>,
+}
+
+/// Re-emits the items recorded to `path`, failing once the recording runs out
+///
+/// A [`Speed::Times`] factor that is zero, negative or not finite fails the stage on start.
+pub fn poll_replay<T>(path: impl Into<PathBuf>, speed: Speed) -> (Poller<Replay<T>>, Receiver<T>)
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let replay = Replay {
+        path: path.into(),
+        speed,
+        _item: std::marker::PhantomData,
+    };
+
+    let (outlet, rx) = Outlet::new(Channel::default());
+
+    (Poller::new(replay, outlet), rx)
+}
+
+impl<T: DeserializeOwned + Send + 'static> Poll for Replay<T> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        if let Speed::Times(factor) = self.speed {
+            anyhow::ensure!(factor.is_finite() && factor > 0.0, "replay speed has to be a finite factor above zero, not {factor}");
+        }
+
+        let mut r = BufReader::new(File::open(&self.path).await?);
+        let start = tokio::time::Instant::now();
+
+        while let Some((at, item)) = disk::read_next::<(u64, T)>(&mut r).await? {
+            let at = Duration::from_micros(at);
+
+            match self.speed {
+                Speed::Original => tokio::time::sleep_until(start + at).await,
+                Speed::Times(factor) => {
+                    // a tiny factor stretches the gaps past what a duration holds
+                    let at = Duration::try_from_secs_f64(at.as_secs_f64() / factor)?;
+                    let due = start.checked_add(at).ok_or_else(|| anyhow::anyhow!("replay gap of {at:?} is too long"))?;
+                    tokio::time::sleep_until(due).await
+                },
+                Speed::Max => {},
+            }
+
+            tx.send(item).await.map_err(|_| SendError(()))?;
+        }
+
+        anyhow::bail!("end of recording {}", self.path.display())
+    }
+}
@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+use std::pin::pin;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+pub use chrono::{DateTime, Utc};
+use chrono::TimeZone;
+use croner::Cron;
+use futures::future::{select, Either};
+
+use crate::channel::{self, Channel, Outlet, Receiver, SendError};
+use crate::io::Poll;
+use crate::pollers::Poller;
+
+/// How long after its time a tick still counts as on time for [`Missed::Skip`]
+const LATE: chrono::TimeDelta = chrono::TimeDelta::seconds(1);
+
+/// A scheduled time came around
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tick<Tz: TimeZone> {
+    /// when it was scheduled for, it fires at or shortly after that
+    pub at: DateTime<Tz>,
+    /// ticks skipped or coalesced since the last one fired
+    pub missed: u64,
+}
+
+/// What to do about ticks that came due while the schedule couldn't fire them, because
+/// the machine was asleep or the stages after it held it up until the next one was due
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Missed {
+    /// drops ticks that are late, the next one on time fires
+    #[default]
+    Skip,
+    /// fires every missed tick, one after another
+    CatchUp,
+    /// fires the latest missed tick once, right away
+    Coalesce,
+}
+
+/// Ticks on a cron schedule, made by [`poll_schedule`]
+pub struct Schedule<Tz: TimeZone> {
+    cron: Cron,
+    tz: Tz,
+    missed: Missed,
+    /// the tick to fire next, kept when the poller restarts for a state update
+    next: Option<DateTime<Tz>>,
+}
+
+/// Ticks whenever `cron_expr` matches, the wall clock time read in `tz`
+///
+/// Takes the usual five fields, `0 2 * * MON-FRI` for every weekday at 02:00, or six
+/// with seconds first. The receiver works as a trigger stream like any other, and also
+/// as state for `with_state`, with a stage acting on each tick as an update.
+#[allow(clippy::type_complexity)]
+pub fn poll_schedule<Tz>(cron_expr: &str, tz: Tz) -> anyhow::Result<(Poller<Schedule<Tz>>, Receiver<Tick<Tz>>)>
+where
+    Tz: TimeZone + Send + Sync + 'static,
+    Tz::Offset: Send + Sync,
+{
+    let cron = Cron::from_str(cron_expr).with_context(|| format!("parsing schedule {cron_expr:?}"))?;
+
+    // holds one tick, so a slow consumer holds up the schedule and it misses ticks
+    let (outlet, rx) = Outlet::new(Channel::bounded(1));
+
+    Ok((Poller::new(Schedule { cron, tz, missed: Missed::Skip, next: None }, outlet), rx))
+}
+
+impl<Tz> Poller<Schedule<Tz>>
+where
+    Tz: TimeZone + Send + Sync + 'static,
+    Tz::Offset: Send + Sync,
+{
+    /// What to do about missed ticks, [`Missed::Skip`] by default
+    pub fn on_missed(self, missed: Missed) -> Self {
+        self.map_poller(|p| Schedule { missed, ..p })
+    }
+}
+
+impl<Tz: TimeZone> Schedule<Tz> {
+    fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.tz)
+    }
+
+    fn after(&self, at: &DateTime<Tz>) -> anyhow::Result<DateTime<Tz>> {
+        Ok(self.cron.find_next_occurrence(at, false)?)
+    }
+}
+
+/// Sleeps until the wall clock reaches `at`
+///
+/// Timers run on a clock that stops while the machine sleeps, so long waits are broken
+/// up to notice the wall clock jumping ahead.
+async fn wait_until<Tz: TimeZone>(at: &DateTime<Tz>) {
+    loop {
+        let Ok(wait) = at.clone().signed_duration_since(Utc::now()).to_std() else {
+            return;
+        };
+
+        if wait.is_zero() {
+            return;
+        }
+
+        tokio::time::sleep(wait.min(Duration::from_secs(60))).await;
+    }
+}
+
+impl<Tz> Poll for Schedule<Tz>
+where
+    Tz: TimeZone + Send + Sync + 'static,
+    Tz::Offset: Send + Sync,
+{
+    type Item = Tick<Tz>;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut at = match self.next.take() {
+            Some(at) => at,
+            None => self.after(&self.now())?,
+        };
+
+        let mut missed = 0;
+
+        loop {
+            self.next = Some(at.clone());
+            wait_until(&at).await;
+
+            let now = self.now();
+            let next = self.after(&at)?;
+
+            // a later tick being due already makes this one missed
+            let fire = match self.missed {
+                Missed::Skip => next > now && now.clone().signed_duration_since(at.clone()) <= LATE,
+                Missed::CatchUp => true,
+                Missed::Coalesce => next > now,
+            };
+
+            if !fire {
+                missed += 1;
+            } else if self.missed == Missed::CatchUp {
+                tx.send(Tick { at, missed }).await.map_err(|_| SendError(()))?;
+            } else {
+                // held up until the next one is due, the tick is missed after all
+                let send = pin!(tx.send(Tick { at, missed }));
+                let due = pin!(wait_until(&next));
+
+                match select(send, due).await {
+                    Either::Left((sent, _)) => {
+                        sent.map_err(|_| SendError(()))?;
+                        missed = 0;
+                    },
+                    Either::Right(_) => missed += 1,
+                }
+            }
+
+            at = next;
+        }
+    }
+}
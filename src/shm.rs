@@ -0,0 +1,388 @@
+use std::convert::Infallible;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering::*};
+use std::time::Duration;
+
+use tokio::net::unix::pipe;
+
+use crate::channel;
+use crate::io::{Poll, Push};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"ppioring");
+
+/// How often a poller looks at the path for a new ring
+const RECHECK: Duration = Duration::from_millis(100);
+
+/// Plain data that can be copied between processes byte for byte
+///
+/// # Safety
+///
+/// Every bit pattern written by one process has to be a valid value in another, so no
+/// pointers, references or handles. `#[repr(C)]` structs of numbers are fine.
+pub unsafe trait Pod: Copy + Send + 'static {}
+
+macro_rules! pod {
+    ($($t:ty),*) => { $(unsafe impl Pod for $t {})* };
+}
+
+pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+#[repr(C, align(64))]
+struct Line<T>(T);
+
+/// Start of the shared file, followed by the slots
+#[repr(C)]
+struct Header {
+    magic: Line<AtomicU64>,
+    capacity: u64,
+    item: u64,
+    /// the poller is waiting on the wakeup pipe
+    waiting: Line<AtomicU32>,
+    /// the pusher is gone, the poller moves on to a new ring once this one is drained
+    closed: Line<AtomicU32>,
+    head: Line<AtomicU64>,
+    tail: Line<AtomicU64>,
+}
+
+struct Ring<T> {
+    ptr: NonNull<u8>,
+    len: usize,
+    ino: u64,
+    /// taken from the header once, the other side could change it afterwards
+    capacity: u64,
+    _item: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T: Pod> Ring<T> {
+    fn len_for(capacity: u64) -> usize {
+        size_of::<Header>() + capacity as usize * size_of::<T>()
+    }
+
+    fn map(file: &File, len: usize, capacity: u64) -> io::Result<Self> {
+        assert!(align_of::<T>() <= align_of::<Header>(), "items are over-aligned for the ring");
+
+        // SAFETY: a shared mapping of a file we hold open, it's unmapped on drop
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                std::os::fd::AsRawFd::as_raw_fd(file),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: NonNull::new(ptr.cast()).unwrap(),
+            len,
+            ino: file.metadata()?.ino(),
+            capacity,
+            _item: PhantomData,
+        })
+    }
+
+    /// Makes a new ring at `path`, replacing the file so a poller of an old one isn't cut off
+    fn create(path: &Path, capacity: u64) -> io::Result<Self> {
+        let tmp = path.with_extension("tmp");
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp)?;
+        file.set_len(Self::len_for(capacity) as u64)?;
+
+        let ring = Self::map(&file, Self::len_for(capacity), capacity)?;
+
+        // SAFETY: the fresh mapping is zeroed, nobody else sees it until the rename
+        unsafe {
+            let header = ring.ptr.as_ptr().cast::<Header>();
+            (*header).capacity = capacity;
+            (*header).item = size_of::<T>() as u64;
+        }
+
+        ring.header().magic.0.store(MAGIC, Release);
+        fs::rename(tmp, path)?;
+
+        Ok(ring)
+    }
+
+    /// Maps the ring at `path`, `None` while there is no finished one yet
+    fn open(path: &Path) -> anyhow::Result<Option<Self>> {
+        let file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let len = file.metadata()?.len() as usize;
+        if len < size_of::<Header>() {
+            return Ok(None);
+        }
+
+        let mut ring = Self::map(&file, len, 0)?;
+        let header = ring.header();
+
+        if header.magic.0.load(Acquire) != MAGIC {
+            return Ok(None);
+        }
+
+        let (capacity, item) = (header.capacity, header.item);
+
+        anyhow::ensure!(capacity > 0, "ring at {} has no slots", path.display());
+        anyhow::ensure!(item == size_of::<T>() as u64, "ring holds items of {item} bytes, not {}", size_of::<T>());
+        let slots = capacity.checked_mul(item).map(|n| n.saturating_add(size_of::<Header>() as u64));
+        anyhow::ensure!(slots.is_some_and(|n| n <= len as u64), "ring at {} is cut short", path.display());
+
+        ring.capacity = capacity;
+        Ok(Some(ring))
+    }
+
+    fn header(&self) -> &Header {
+        // SAFETY: the mapping is at least a header long and page aligned
+        unsafe { &*self.ptr.as_ptr().cast::<Header>() }
+    }
+
+    fn slot(&self, i: u64) -> *mut T {
+        let offset = size_of::<Header>() + (i % self.capacity) as usize * size_of::<T>();
+
+        // SAFETY: within the mapping, see `len_for`
+        unsafe { self.ptr.as_ptr().add(offset).cast() }
+    }
+
+    fn try_push(&self, item: T) -> Result<(), T> {
+        let header = self.header();
+        let tail = header.tail.0.load(Relaxed);
+
+        if tail - header.head.0.load(Acquire) == self.capacity {
+            return Err(item);
+        }
+
+        // SAFETY: the slot is free until `tail` moves past it, and only this side writes
+        unsafe { self.slot(tail).write_volatile(item) };
+        header.tail.0.store(tail + 1, SeqCst);
+
+        Ok(())
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        let header = self.header();
+        let head = header.head.0.load(Relaxed);
+
+        if head == header.tail.0.load(Acquire) {
+            return None;
+        }
+
+        // SAFETY: the slot was published by the store to `tail`
+        let item = unsafe { self.slot(head).read_volatile() };
+        header.head.0.store(head + 1, Release);
+
+        Some(item)
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        // SAFETY: mapped in `Ring::map` with this length
+        unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+fn wake_path(path: &Path) -> PathBuf {
+    path.with_extension("wake")
+}
+
+fn make_fifo(path: &Path) -> io::Result<()> {
+    let cpath = std::ffi::CString::new(path.as_os_str().as_encoded_bytes())?;
+
+    // SAFETY: a valid nul terminated path
+    match unsafe { libc::mkfifo(cpath.as_ptr(), 0o600) } {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            e if e.kind() == ErrorKind::AlreadyExists => Ok(()),
+            e => Err(e),
+        },
+    }
+}
+
+/// Writes items into a shared-memory ring for a [`ShmPoller`] in another process
+///
+/// Meant for fixed size [`Pod`] records at high rates, a push is a copy into the
+/// mapping. While the ring is full the pusher backs off and retries.
+pub struct ShmPusher<T: Pod> {
+    path: PathBuf,
+    capacity: u64,
+    ring: Option<Ring<T>>,
+    wake: Option<File>,
+}
+
+impl<T: Pod> ShmPusher<T> {
+    /// Creates a ring of `capacity` items at `path` (usually under `/dev/shm`) on the first push
+    ///
+    /// A wakeup pipe is created next to it, with a `.wake` extension.
+    pub fn create(path: impl Into<PathBuf>, capacity: u64) -> Self {
+        Self {
+            path: path.into(),
+            capacity: capacity.max(1),
+            ring: None,
+            wake: None,
+        }
+    }
+
+    fn wake(&mut self) -> io::Result<()> {
+        if self.wake.is_none() {
+            make_fifo(&wake_path(&self.path))?;
+
+            // read-write never blocks on a fifo, even before the poller opened it
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(wake_path(&self.path))?;
+
+            self.wake = Some(file);
+        }
+
+        match self.wake.as_ref().unwrap().write(&[1]) {
+            Err(e) if e.kind() != ErrorKind::WouldBlock => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: Pod> Drop for ShmPusher<T> {
+    fn drop(&mut self) {
+        if let Some(ring) = &self.ring {
+            ring.header().closed.0.store(1, SeqCst);
+            let _ = self.wake();
+        }
+    }
+}
+
+impl<T: Pod> Push<T> for ShmPusher<T> {
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        if self.ring.is_none() {
+            self.ring = Some(Ring::create(&self.path, self.capacity)?);
+        }
+
+        let mut item = item;
+        let mut delay = Duration::from_micros(50);
+
+        loop {
+            let ring = self.ring.as_ref().unwrap();
+
+            match ring.try_push(item) {
+                Ok(()) => break,
+                Err(back) => item = back,
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(Duration::from_millis(10));
+        }
+
+        if self.ring.as_ref().unwrap().header().waiting.0.load(SeqCst) == 1 {
+            self.wake()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads items a [`ShmPusher`] in another process writes, see there
+///
+/// Sleeps on the wakeup pipe while the ring is empty. When the pusher goes away it
+/// drains the ring and waits for the next one created at the same path. A pusher that
+/// crashed can't say it's gone, so an idle poller also looks at the path every so
+/// often and moves over once a new ring replaced its own.
+pub struct ShmPoller<T: Pod> {
+    path: PathBuf,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: Pod> ShmPoller<T> {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), _item: PhantomData }
+    }
+
+    async fn next_ring(&self, previous: Option<u64>) -> anyhow::Result<Ring<T>> {
+        loop {
+            if let Some(ring) = Ring::open(&self.path)? {
+                if Some(ring.ino) != previous {
+                    return Ok(ring);
+                }
+            }
+
+            tokio::time::sleep(RECHECK).await;
+        }
+    }
+
+    /// Whether another ring than `ring` is at the path by now
+    fn replaced(&self, ring: &Ring<T>) -> io::Result<bool> {
+        match fs::metadata(&self.path) {
+            Ok(meta) => Ok(meta.ino() != ring.ino),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<T: Pod> Poll for ShmPoller<T> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        make_fifo(&wake_path(&self.path))?;
+
+        // opened read-write so it never sees the end of the pipe
+        let wake = pipe::OpenOptions::new().read_write(true).open_receiver(wake_path(&self.path))?;
+        let mut ring = self.next_ring(None).await?;
+
+        loop {
+            while let Some(item) = ring.try_pop() {
+                tx.send(item).await.map_err(|_| channel::SendError(()))?;
+            }
+
+            let header = ring.header();
+
+            if header.closed.0.load(SeqCst) == 1 {
+                if header.head.0.load(SeqCst) == header.tail.0.load(SeqCst) {
+                    ring = self.next_ring(Some(ring.ino)).await?;
+                }
+
+                continue;
+            }
+
+            header.waiting.0.store(1, SeqCst);
+
+            // anything pushed before `waiting` was seen needs no wakeup
+            if header.head.0.load(SeqCst) == header.tail.0.load(SeqCst) {
+                if let Ok(woken) = tokio::time::timeout(RECHECK, wake.readable()).await {
+                    woken?;
+
+                    let mut buf = [0; 64];
+                    while wake.try_read(&mut buf).is_ok_and(|n| n > 0) {}
+                }
+            }
+
+            header.waiting.0.store(0, SeqCst);
+
+            if self.replaced(&ring)? {
+                while let Some(item) = ring.try_pop() {
+                    tx.send(item).await.map_err(|_| channel::SendError(()))?;
+                }
+
+                ring = self.next_ring(Some(ring.ino)).await?;
+            }
+        }
+    }
+}
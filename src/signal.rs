@@ -0,0 +1,266 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::future::IntoFuture;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use futures::future::{select, BoxFuture, Either};
+use futures::stream::{self, StreamExt};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::AbortHandle;
+
+use crate::channel::{self, bounded, Channel, Outlet, Receiver, Sender};
+use crate::io::Poll;
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// The unix signals ppio listens for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Hup,
+    Int,
+    Term,
+    Quit,
+    Usr1,
+    Usr2,
+}
+
+impl Signal {
+    fn kind(self) -> SignalKind {
+        match self {
+            Self::Hup => SignalKind::hangup(),
+            Self::Int => SignalKind::interrupt(),
+            Self::Term => SignalKind::terminate(),
+            Self::Quit => SignalKind::quit(),
+            Self::Usr1 => SignalKind::user_defined1(),
+            Self::Usr2 => SignalKind::user_defined2(),
+        }
+    }
+
+    fn number(self) -> libc::c_int {
+        match self {
+            Self::Hup => libc::SIGHUP,
+            Self::Int => libc::SIGINT,
+            Self::Term => libc::SIGTERM,
+            Self::Quit => libc::SIGQUIT,
+            Self::Usr1 => libc::SIGUSR1,
+            Self::Usr2 => libc::SIGUSR2,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Hup => "SIGHUP",
+            Self::Int => "SIGINT",
+            Self::Term => "SIGTERM",
+            Self::Quit => "SIGQUIT",
+            Self::Usr1 => "SIGUSR1",
+            Self::Usr2 => "SIGUSR2",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Waits for any of `signals`, as a stream
+fn listen(signals: &[Signal]) -> anyhow::Result<impl futures::Stream<Item = Signal> + Unpin> {
+    let mut streams = Vec::new();
+
+    for &sig in signals {
+        let mut listener = signal(sig.kind())?;
+        streams.push(stream::poll_fn(move |cx| listener.poll_recv(cx).map(|got| got.map(|()| sig))));
+    }
+
+    Ok(stream::select_all(streams))
+}
+
+/// Emits the signals it listens for, made by [`poll_signals`]
+pub struct Signals {
+    signals: Vec<Signal>,
+}
+
+/// Emits every one of `signals` the process gets
+///
+/// The receiver can go straight into `with_state`, to reload configuration on SIGHUP
+/// for instance. Listening replaces the default action of those signals for good, so
+/// SIGTERM or SIGINT won't end the process by themselves anymore, see [`Shutdown`].
+pub fn poll_signals(signals: &[Signal]) -> (Poller<Signals>, Receiver<Signal>) {
+    let (outlet, rx) = Outlet::new(Channel::default());
+
+    (Poller::new(Signals { signals: signals.to_vec() }, outlet), rx)
+}
+
+impl Poll for Signals {
+    type Item = Signal;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut signals = listen(&self.signals)?;
+
+        while let Some(sig) = signals.next().await {
+            tx.send(sig).await?;
+        }
+
+        anyhow::bail!("stopped listening for signals")
+    }
+}
+
+/// Stops a pipeline gracefully
+///
+/// Sources wrapped with [`Shutdown::until`] are dropped once it is triggered, closing
+/// their channels, so the stages after them finish what's in flight and stop too, the
+/// same way a pipeline ends at the end of its input. Clones trigger the same shutdown.
+#[derive(Clone)]
+pub struct Shutdown {
+    // never sent on, closing it is the trigger every receiver sees
+    tx: Sender<()>,
+    rx: Receiver<()>,
+    /// what [`OnSignals`] keeps listening with while the pipeline drains
+    listener: Arc<Mutex<Option<AbortHandle>>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (tx, rx) = bounded(1);
+        Self { tx, rx, listener: Arc::default() }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self) {
+        self.tx.close();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Resolves once the shutdown is triggered
+    pub async fn triggered(&self) {
+        let _ = self.rx.recv().await;
+    }
+
+    /// Runs `stage` until the shutdown, then drops it
+    ///
+    /// Meant for the sources of a pipeline, dropping one closes its channel.
+    pub fn until<F>(&self, stage: F) -> Until
+    where
+        F: IntoFuture<Output = Result<Infallible, crate::Error>>,
+        F::IntoFuture: Send + 'static,
+    {
+        Until { shutdown: self.clone(), stage: Box::pin(stage.into_future()) }
+    }
+
+    /// A stage triggering the shutdown on SIGTERM or SIGINT
+    ///
+    /// It ends with the trigger, so it doesn't hold up the pipeline it runs in. Signals
+    /// after the first are only logged, unless [`OnSignals::force_exit_on_second`], until
+    /// the pipeline run by [`Shutdown::drain`] is done.
+    pub fn on_signals(&self) -> OnSignals {
+        OnSignals { shutdown: self.clone(), force_exit: false }
+    }
+
+    /// Runs `pipeline` to the end, then stops listening for signals
+    ///
+    /// Once it's done, SIGTERM and SIGINT end the process again like they did before
+    /// [`Shutdown::on_signals`] took them over.
+    pub async fn drain<F: IntoFuture>(&self, pipeline: F) -> F::Output {
+        let res = pipeline.await;
+
+        if let Some(listener) = self.listener.lock().unwrap().take() {
+            listener.abort();
+
+            for sig in [Signal::Term, Signal::Int] {
+                // SAFETY: puts back the default action, no handler of ours is involved
+                unsafe { libc::signal(sig.number(), libc::SIG_DFL) };
+            }
+        }
+
+        res
+    }
+}
+
+/// Runs a stage until a shutdown, see [`Shutdown::until`]
+pub struct Until {
+    shutdown: Shutdown,
+    stage: BoxFuture<'static, Result<Infallible, crate::Error>>,
+}
+
+impl IntoFuture for Until {
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let triggered = pin!(self.shutdown.triggered());
+
+            match select(self.stage, triggered).await {
+                Either::Left((res, _)) => res,
+                Either::Right(_) => Err(Internal(anyhow!("shutting down"))),
+            }
+        })
+    }
+}
+
+/// Triggers a [`Shutdown`] on SIGTERM or SIGINT, see [`Shutdown::on_signals`]
+pub struct OnSignals {
+    shutdown: Shutdown,
+    force_exit: bool,
+}
+
+impl OnSignals {
+    /// Exits the process right away on a second signal, with the usual `128 + n` status
+    ///
+    /// For when draining takes too long, nothing still in flight is finished or flushed.
+    pub fn force_exit_on_second(self) -> Self {
+        Self { force_exit: true, ..self }
+    }
+}
+
+impl IntoFuture for OnSignals {
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let mut signals = listen(&[Signal::Term, Signal::Int]).map_err(User)?;
+
+            let first = {
+                let got = pin!(signals.next());
+                let triggered = pin!(self.shutdown.triggered());
+
+                match select(got, triggered).await {
+                    Either::Left((sig, _)) => sig,
+                    Either::Right(_) => None,
+                }
+            };
+
+            self.shutdown.trigger();
+
+            let force_exit = self.force_exit;
+
+            let listener = tokio::spawn(async move {
+                while let Some(sig) = signals.next().await {
+                    if force_exit {
+                        std::process::exit(128 + sig.number());
+                    }
+
+                    log::warn!("got {sig} while shutting down, still draining");
+                }
+            });
+
+            *self.shutdown.listener.lock().unwrap() = Some(listener.abort_handle());
+
+            Err(Internal(match first {
+                Some(sig) => anyhow!("shutting down on {sig}"),
+                None => anyhow!("shutting down"),
+            }))
+        })
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
+use std::future::IntoFuture;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{select, select_all, BoxFuture, Either};
+
+use crate::channel::{bounded, unbounded, Channel, Outlet, Receiver, RecvError, SendError, Sender};
+use crate::checkpoint::{Checkpoint, Store};
+use crate::io::{Poll, Push};
+use crate::pollers::Poller;
+use crate::Error::*;
+
+pub type Epoch = u64;
+
+const COMMITTED: &str = "epoch";
+
+/// What flows between stages taking part in pipeline-wide snapshots
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Marked<T> {
+    Item(T),
+    /// Everything before this belongs to the snapshot for the epoch
+    Barrier(Epoch),
+}
+
+/// Coordinates consistent snapshots across a pipeline
+///
+/// Sources inject a barrier on every [`Barriers::trigger`], and each stage snapshots its
+/// state once the barrier arrived on all of its inputs, so that a snapshot holds exactly
+/// the effects of the items the sources emitted before it. An epoch is committed to the
+/// store once every source and stage has reported in, so they all have to be created
+/// before the first trigger. They restore the last committed epoch before handling any
+/// items. One that ran out of input counts with its last snapshot from then on. Stages in
+/// between that keep no state pass barriers on with [`Barriers::through`].
+#[derive(Clone)]
+pub struct Barriers(Arc<Inner>);
+
+struct Inner {
+    store: Arc<dyn Store>,
+    committed: Option<Epoch>,
+    coord: Mutex<Coord>,
+    /// the last epoch in the store, held while one is being committed
+    stored: futures::lock::Mutex<Option<Epoch>>,
+}
+
+#[derive(Default)]
+struct Coord {
+    /// keys of every source and stage, fixed once the first barrier is out
+    members: HashSet<String>,
+    /// the last snapshots of members that finished
+    finished: HashMap<String, Vec<u8>>,
+    sealed: bool,
+    sources: Vec<Sender<Epoch>>,
+    next: Epoch,
+    pending: BTreeMap<Epoch, HashMap<String, Vec<u8>>>,
+    committed: Option<Epoch>,
+}
+
+impl Barriers {
+    pub async fn new(store: impl Store + 'static) -> anyhow::Result<Self> {
+        let committed = match store.load(COMMITTED).await? {
+            Some(bytes) => Some(bincode::deserialize(&bytes)?),
+            None => None,
+        };
+
+        let coord = Coord {
+            next: committed.map_or(0, |e| e + 1),
+            committed,
+            ..Default::default()
+        };
+
+        Ok(Self(Arc::new(Inner {
+            store: Arc::new(store),
+            committed,
+            coord: Mutex::new(coord),
+            stored: futures::lock::Mutex::new(committed),
+        })))
+    }
+
+    /// The epoch everything was restored to
+    pub fn restored(&self) -> Option<Epoch> {
+        self.0.committed
+    }
+
+    /// The last epoch every source and stage has snapshotted
+    pub fn committed(&self) -> Option<Epoch> {
+        self.0.coord.lock().unwrap().committed
+    }
+
+    /// Starts a new epoch by injecting a barrier at every source
+    pub fn trigger(&self) -> Epoch {
+        let mut coord = self.0.coord.lock().unwrap();
+        let epoch = coord.next;
+
+        coord.sealed = true;
+        coord.next += 1;
+        coord.sources.retain(|tx| tx.try_send(epoch).is_ok());
+
+        epoch
+    }
+
+    /// How many items the source under `key` had passed on as of the restored epoch,
+    /// where its poller should pick up again
+    pub async fn offset(&self, key: &str) -> anyhow::Result<u64> {
+        match self.load(key).await? {
+            Some(bytes) => Ok(bincode::deserialize(&bytes)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Puts a barrier injecting source after `rx`, see [`Barriers::offset`] for resuming
+    #[allow(clippy::type_complexity)]
+    pub async fn source<T: Send>(&self, rx: Receiver<T>, key: impl Into<String>) -> anyhow::Result<(Poller<BarrierSource<T>>, Receiver<Marked<T>>)> {
+        let key = key.into();
+        let offset = self.offset(&key).await?;
+
+        let (trigger_tx, trigger_rx) = unbounded();
+        let (outlet, out) = Outlet::new(Channel::bounded(1));
+
+        let mut coord = self.0.coord.lock().unwrap();
+        coord.join(&key)?;
+        coord.sources.push(trigger_tx);
+
+        let source = BarrierSource {
+            barriers: self.clone(),
+            key,
+            recver: rx,
+            trigger: trigger_rx,
+            offset,
+        };
+
+        Ok((Poller::new(source, outlet), out))
+    }
+
+    /// Runs `pusher` over `inputs`, aligning barriers between them before every snapshot
+    pub async fn stage<T, C: Push<T> + Checkpoint>(
+        &self,
+        inputs: impl IntoIterator<Item = Receiver<Marked<T>>>,
+        mut pusher: C,
+        key: impl Into<String>,
+    ) -> anyhow::Result<AlignedPusher<T, C>> {
+        let key = key.into();
+        self.0.coord.lock().unwrap().join(&key)?;
+
+        if let Some(bytes) = self.load(&key).await? {
+            pusher.restore(bincode::deserialize(&bytes)?);
+        }
+
+        Ok(AlignedPusher {
+            barriers: self.clone(),
+            key,
+            inputs: inputs.into_iter().collect(),
+            pusher,
+            forward: None,
+        })
+    }
+
+    /// Runs a stage without state of its own between snapshotting ones
+    ///
+    /// `make` gets the sender for the stage's output and builds a regular pusher, a map or
+    /// a filter say, that sees plain items. A barrier goes out once everything the pusher
+    /// sent for the items before it did. Only the output of the push in progress is held
+    /// back, in an unbounded channel.
+    pub fn through<T, U, P: Push<T>>(
+        &self,
+        rx: Receiver<Marked<T>>,
+        make: impl FnOnce(Sender<U>) -> P,
+    ) -> (Through<T, U, P>, Receiver<Marked<U>>) {
+        let (relay_tx, relay_rx) = unbounded();
+        let (tx, out) = bounded(1);
+
+        let through = Through {
+            recver: rx,
+            pusher: make(relay_tx),
+            relay: relay_rx,
+            sender: tx,
+        };
+
+        (through, out)
+    }
+
+    async fn load(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.0.committed {
+            Some(epoch) => self.0.store.load(&format!("{key}@{epoch}")).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Records a member's snapshot, committing the epoch once everyone is in
+    async fn ack(&self, key: &str, epoch: Epoch, snapshot: Vec<u8>) -> anyhow::Result<()> {
+        let ready = {
+            let mut coord = self.0.coord.lock().unwrap();
+
+            coord.pending.entry(epoch).or_default().insert(key.to_owned(), snapshot);
+            coord.take_ready()
+        };
+
+        self.commit(ready).await
+    }
+
+    /// A member ran out of input, its last snapshot stands in for it in every later epoch
+    async fn finish(&self, key: &str, snapshot: Vec<u8>) -> anyhow::Result<()> {
+        let ready = {
+            let mut coord = self.0.coord.lock().unwrap();
+
+            coord.finished.insert(key.to_owned(), snapshot);
+            coord.take_ready()
+        };
+
+        self.commit(ready).await
+    }
+
+    async fn commit(&self, ready: Option<(Epoch, HashMap<String, Vec<u8>>)>) -> anyhow::Result<()> {
+        let Some((epoch, snapshots)) = ready else {
+            return Ok(());
+        };
+
+        let mut stored = self.0.stored.lock().await;
+
+        // another member may have committed a later epoch in the meantime
+        if stored.is_some_and(|e| e >= epoch) {
+            return Ok(());
+        }
+
+        let store = &self.0.store;
+
+        for (key, bytes) in &snapshots {
+            store.save(&format!("{key}@{epoch}"), bytes).await?;
+        }
+
+        // the epoch marker is the commit point, only then is the previous epoch garbage
+        store.save(COMMITTED, &bincode::serialize(&epoch)?).await?;
+
+        if let Some(previous) = *stored {
+            for key in snapshots.keys() {
+                store.remove(&format!("{key}@{previous}")).await?;
+            }
+        }
+
+        *stored = Some(epoch);
+        self.0.coord.lock().unwrap().committed = Some(epoch);
+
+        Ok(())
+    }
+}
+
+impl Coord {
+    fn join(&mut self, key: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.sealed, "{key} joined the snapshots after the first barrier, create every source and stage before triggering");
+        anyhow::ensure!(self.members.insert(key.to_owned()), "{key} is already taking part in the snapshots");
+
+        Ok(())
+    }
+
+    /// Takes the latest epoch every member is in for, along with the ones before it
+    fn take_ready(&mut self) -> Option<(Epoch, HashMap<String, Vec<u8>>)> {
+        let epoch = self.pending.iter().rev().find_map(|(&epoch, snapshots)| {
+            let ready = self.members.iter().all(|key| snapshots.contains_key(key) || self.finished.contains_key(key));
+            ready.then_some(epoch)
+        })?;
+
+        let mut snapshots = self.pending.remove(&epoch).unwrap();
+        self.pending.retain(|&e, _| e > epoch);
+
+        for (key, bytes) in &self.finished {
+            snapshots.entry(key.clone()).or_insert_with(|| bytes.clone());
+        }
+
+        Some((epoch, snapshots))
+    }
+}
+
+/// Injects barriers into a stream, made by [`Barriers::source`]
+pub struct BarrierSource<T> {
+    barriers: Barriers,
+    key: String,
+    recver: Receiver<T>,
+    trigger: Receiver<Epoch>,
+    offset: u64,
+}
+
+impl<T: Send> Poll for BarrierSource<T> {
+    type Item = Marked<T>;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        loop {
+            let marked = match select(pin!(self.recver.recv()), pin!(self.trigger.recv())).await {
+                Either::Left((Ok(item), _)) => {
+                    self.offset += 1;
+                    Marked::Item(item)
+                },
+                Either::Left((Err(e), _)) => {
+                    self.barriers.finish(&self.key, bincode::serialize(&self.offset)?).await?;
+                    return Err(Internal(e.into()).into());
+                },
+                Either::Right((Ok(epoch), _)) => {
+                    self.barriers.ack(&self.key, epoch, bincode::serialize(&self.offset)?).await?;
+                    Marked::Barrier(epoch)
+                },
+                Either::Right((Err(e), _)) => return Err(Internal(e.into()).into()),
+            };
+
+            tx.send(marked).await.map_err(|_| Internal(SendError(()).into()))?;
+        }
+    }
+}
+
+type Forward = Box<dyn Fn(Epoch) -> BoxFuture<'static, bool> + Send>;
+
+/// A pusher taking part in snapshots, made by [`Barriers::stage`]
+pub struct AlignedPusher<T, C> {
+    barriers: Barriers,
+    key: String,
+    inputs: Vec<Receiver<Marked<T>>>,
+    pusher: C,
+    forward: Option<Forward>,
+}
+
+impl<T, C> AlignedPusher<T, C> {
+    /// Passes barriers on to the next stage, after everything `pusher` sent for the epoch
+    ///
+    /// The pusher should send its own output as [`Marked::Item`] on a clone of `tx`.
+    pub fn forward<U: Send + 'static>(mut self, tx: Sender<Marked<U>>) -> Self {
+        self.forward = Some(Box::new(move |epoch| {
+            let tx = tx.clone();
+            Box::pin(async move { tx.send(Marked::Barrier(epoch)).await.is_ok() })
+        }));
+        self
+    }
+}
+
+impl<T, C> IntoFuture for AlignedPusher<T, C>
+where
+    T: Send + 'static,
+    C: Push<T> + Checkpoint + Send + 'static,
+{
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        Box::pin(async move {
+            let mut closed = vec![false; self.inputs.len()];
+            let mut blocked = vec![false; self.inputs.len()];
+            let mut epoch = None;
+
+            loop {
+                let live: Vec<_> = (0..self.inputs.len()).filter(|&i| !closed[i] && !blocked[i]).collect();
+
+                if live.is_empty() {
+                    let snapshot = bincode::serialize(&self.pusher.snapshot()).map_err(|e| User(e.into()))?;
+                    self.barriers.finish(&self.key, snapshot).await.map_err(User)?;
+
+                    return Err(Internal(RecvError.into()));
+                }
+
+                let recvs = live.iter().map(|&i| Box::pin(self.inputs[i].recv()));
+                let (res, n, _) = select_all(recvs).await;
+                let i = live[n];
+
+                match res {
+                    Ok(Marked::Item(item)) => {
+                        self.pusher.push(item).await.map_err(User)?;
+                        continue;
+                    },
+                    Ok(Marked::Barrier(e)) => {
+                        blocked[i] = true;
+                        epoch = Some(e);
+                    },
+                    Err(_) => closed[i] = true,
+                }
+
+                // aligned once the barrier is in on every input that's still open
+                let aligned = (0..self.inputs.len()).all(|i| closed[i] || blocked[i]);
+
+                if let (true, Some(e)) = (aligned, epoch) {
+                    let snapshot = bincode::serialize(&self.pusher.snapshot()).map_err(|e| User(e.into()))?;
+                    self.barriers.ack(&self.key, e, snapshot).await.map_err(User)?;
+
+                    if let Some(forward) = &self.forward {
+                        if !forward(e).await {
+                            return Err(Internal(SendError(()).into()));
+                        }
+                    }
+
+                    blocked.fill(false);
+                    epoch = None;
+                }
+            }
+        })
+    }
+}
+
+/// A stage without state passing barriers on, made by [`Barriers::through`]
+pub struct Through<T, U, P> {
+    recver: Receiver<Marked<T>>,
+    pusher: P,
+    relay: Receiver<U>,
+    sender: Sender<Marked<U>>,
+}
+
+impl<T, U, P> IntoFuture for Through<T, U, P>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    P: Push<T> + Send + 'static,
+{
+    type Output = Result<Infallible, crate::Error>;
+    type IntoFuture = BoxFuture<'static, Self::Output>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        Box::pin(async move {
+            loop {
+                let barrier = match self.recver.recv().await.map_err(|e| Internal(e.into()))? {
+                    Marked::Item(item) => {
+                        self.pusher.push(item).await.map_err(User)?;
+                        None
+                    },
+                    Marked::Barrier(epoch) => Some(epoch),
+                };
+
+                // the push is done, so everything it sent is in the relay by now
+                while let Ok(item) = self.relay.try_recv() {
+                    self.sender.send(Marked::Item(item)).await.map_err(|_| Internal(SendError(()).into()))?;
+                }
+
+                if let Some(epoch) = barrier {
+                    self.sender.send(Marked::Barrier(epoch)).await.map_err(|_| Internal(SendError(()).into()))?;
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::pin::pin;
+
+use futures::future::{select, BoxFuture, Either};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::channel::{Channel, Outlet, Receiver, RecvError, SendError, Sender};
+use crate::disk;
+use crate::io::Poll;
+use crate::pollers::Poller;
+use crate::Error::*;
+
+const EXT: &str = "spill";
+
+/// Sits between two stages, spilling items to disk while the consumer can't keep up
+///
+/// Items are kept in order: up to `memory` of them wait in memory, the rest are appended
+/// to segment files in `dir` and read back one segment at a time once the consumer
+/// catches up. Segments left over from a previous run are removed on start.
+pub struct Spill<T> {
+    recver: Receiver<T>,
+    dir: PathBuf,
+    memory: usize,
+    segment: usize,
+}
+
+/// Puts a [`Spill`] after `rx`, the returned receiver gets the same items in the same order
+pub fn spill<T: Serialize + DeserializeOwned + Send + 'static>(rx: Receiver<T>, dir: impl Into<PathBuf>) -> (Poller<Spill<T>>, Receiver<T>) {
+    let (outlet, out) = Outlet::new(Channel::bounded(1));
+
+    let spill = Spill {
+        recver: rx,
+        dir: dir.into(),
+        memory: 1024,
+        segment: 1024,
+    };
+
+    (Poller::new(spill, outlet), out)
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> Poller<Spill<T>> {
+    /// Items kept in memory before spilling, 1024 by default
+    pub fn memory(self, items: usize) -> Self {
+        self.map_poller(|spill| Spill { memory: items, ..spill })
+    }
+
+    /// Items per segment file, 1024 by default
+    pub fn segment(self, items: usize) -> Self {
+        self.map_poller(|spill| Spill { segment: items.max(1), ..spill })
+    }
+}
+
+struct Queue<T> {
+    dir: PathBuf,
+    memory: usize,
+    segment: usize,
+    head: VecDeque<T>,
+    /// full segments waiting to be read back, oldest first
+    spilled: VecDeque<PathBuf>,
+    writing: Option<(PathBuf, BufWriter<File>, usize)>,
+    next: u64,
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> {
+    async fn open(dir: PathBuf, memory: usize, segment: usize) -> anyhow::Result<Self> {
+        fs::create_dir_all(&dir).await?;
+
+        for (_, path) in disk::segments(&dir, EXT).await? {
+            fs::remove_file(path).await?;
+        }
+
+        Ok(Self {
+            dir,
+            memory,
+            segment,
+            head: VecDeque::new(),
+            spilled: VecDeque::new(),
+            writing: None,
+            next: 0,
+        })
+    }
+
+    fn is_spilling(&self) -> bool {
+        !self.spilled.is_empty() || self.writing.is_some()
+    }
+
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        if !self.is_spilling() && self.head.len() < self.memory {
+            self.head.push_back(item);
+            return Ok(());
+        }
+
+        if self.writing.is_none() {
+            let path = disk::segment_path(&self.dir, self.next, EXT);
+            let file = File::create(&path).await?;
+
+            self.writing = Some((path, BufWriter::new(file), 0));
+            self.next += 1;
+        }
+
+        let (_, w, count) = self.writing.as_mut().unwrap();
+        w.write_all(&disk::encode(&item)?).await?;
+        *count += 1;
+
+        if *count >= self.segment {
+            self.seal().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Finishes the segment being written, so that it can be read back
+    async fn seal(&mut self) -> anyhow::Result<()> {
+        if let Some((path, mut w, _)) = self.writing.take() {
+            w.flush().await?;
+            self.spilled.push_back(path);
+        }
+
+        Ok(())
+    }
+
+    async fn pop(&mut self) -> anyhow::Result<Option<T>> {
+        if self.head.is_empty() && self.is_spilling() {
+            if self.spilled.is_empty() {
+                self.seal().await?;
+            }
+
+            let path = self.spilled.pop_front().unwrap();
+            self.head.extend(disk::read_all(&path).await?);
+            fs::remove_file(path).await?;
+        }
+
+        Ok(self.head.pop_front())
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> Poll for Spill<T> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut queue = Queue::open(self.dir.clone(), self.memory, self.segment).await?;
+        let mut sending: Option<BoxFuture<'static, Result<(), SendError<T>>>> = None;
+        let mut closed = false;
+
+        loop {
+            if sending.is_none() {
+                if let Some(item) = queue.pop().await? {
+                    let tx = tx.clone();
+                    sending = Some(Box::pin(async move { tx.send(item).await }));
+                }
+            }
+
+            match (sending.take(), closed) {
+                (None, true) => return Err(Internal(RecvError.into()).into()),
+                (Some(send), true) => send.await.map_err(|_| Internal(SendError(()).into()))?,
+                (None, false) => match self.recver.recv().await {
+                    Ok(item) => queue.push(item).await?,
+                    Err(_) => closed = true,
+                },
+                (Some(send), false) => match select(pin!(self.recver.recv()), send).await {
+                    Either::Left((Ok(item), send)) => {
+                        sending = Some(send);
+                        queue.push(item).await?;
+                    },
+                    Either::Left((Err(_), send)) => {
+                        sending = Some(send);
+                        closed = true;
+                    },
+                    Either::Right((res, _)) => res.map_err(|_| Internal(SendError(()).into()))?,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::{pin::Pin, task, task::Poll::*};
+
+use futures::channel::oneshot;
+use futures::Stream;
+use pin_project_lite::pin_project;
+
+use crate::channel::{unbounded, Receiver, SendError, Sender};
+
+pub(crate) type Ack = oneshot::Sender<anyhow::Result<()>>;
+
+struct Acked<S> {
+    state: S,
+    ack: Ack,
+}
+
+/// Sending half of an acked state channel, see [`acked`]
+pub struct AckedSender<S> {
+    sender: Sender<Acked<S>>,
+}
+
+impl<S> Clone for AckedSender<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<S> AckedSender<S> {
+    /// Resolves once the stage has applied `state`, or with the reason it didn't
+    pub async fn send_and_wait(&self, state: S) -> anyhow::Result<()> {
+        let (ack, applied) = oneshot::channel();
+
+        self.sender
+            .send(Acked { state, ack })
+            .await
+            .map_err(|_| SendError(()))?;
+
+        applied
+            .await
+            .map_err(|_| anyhow::anyhow!("stage stopped before applying the state"))?
+    }
+}
+
+pin_project! {
+    /// Receiving half of an acked state channel, handed to `with_state`
+    pub struct AckedReceiver<S> {
+        #[pin]
+        recver: Receiver<Acked<S>>,
+    }
+}
+
+/// State channel whose sender can wait for the receiving stage to apply each state
+pub fn acked<S>() -> (AckedSender<S>, AckedReceiver<S>) {
+    let (tx, rx) = unbounded();
+
+    (AckedSender { sender: tx }, AckedReceiver { recver: rx })
+}
+
+/// Hands every state to a set of stages, see [`StateBus::send_and_wait`]
+pub struct StateBus<S> {
+    senders: Vec<AckedSender<S>>,
+}
+
+impl<S> Default for StateBus<S> {
+    fn default() -> Self {
+        Self {
+            senders: Vec::new(),
+        }
+    }
+}
+
+impl<S: Clone> StateBus<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self) -> AckedReceiver<S> {
+        let (tx, rx) = acked();
+        self.senders.push(tx);
+        rx
+    }
+
+    /// Resolves once every subscriber has applied `state`, or with the first reason one didn't
+    pub async fn send_and_wait(&self, state: S) -> anyhow::Result<()> {
+        let acks = self
+            .senders
+            .iter()
+            .map(|sender| sender.send_and_wait(state.clone()));
+
+        futures::future::try_join_all(acks).await?;
+
+        Ok(())
+    }
+}
+
+pin_project! {
+    /// Anything a stateful stage can take its state from
+    #[project = StateReceiverProj]
+    pub enum StateReceiver<S> {
+        Plain {
+            #[pin]
+            recver: Receiver<S>,
+        },
+        Acked {
+            #[pin]
+            recver: AckedReceiver<S>,
+        },
+    }
+}
+
+impl<S> StateReceiver<S> {
+    pub(crate) fn poll_state(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<(S, Option<Ack>)>> {
+        match self.project() {
+            StateReceiverProj::Plain { recver } => recver
+                .poll_next(cx)
+                .map(|state| state.map(|state| (state, None))),
+            StateReceiverProj::Acked { recver } => match recver.project().recver.poll_next(cx) {
+                Ready(Some(Acked { state, ack })) => Ready(Some((state, Some(ack)))),
+                Ready(None) => Ready(None),
+                Pending => Pending,
+            },
+        }
+    }
+}
+
+pub trait IntoStateReceiver<S> {
+    fn into_state_receiver(self) -> StateReceiver<S>;
+}
+
+impl<S> IntoStateReceiver<S> for StateReceiver<S> {
+    fn into_state_receiver(self) -> StateReceiver<S> {
+        self
+    }
+}
+
+impl<S> IntoStateReceiver<S> for Receiver<S> {
+    fn into_state_receiver(self) -> StateReceiver<S> {
+        StateReceiver::Plain { recver: self }
+    }
+}
+
+impl<S> IntoStateReceiver<S> for AckedReceiver<S> {
+    fn into_state_receiver(self) -> StateReceiver<S> {
+        StateReceiver::Acked { recver: self }
+    }
+}
@@ -0,0 +1,154 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, Context};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Stdin, Stdout};
+
+use crate::channel::{Channel, Outlet, Receiver, SendError, Sender};
+use crate::codec::{Codec, Framing, Text, MAX_FRAME};
+use crate::io::{Poll, Push};
+use crate::pollers::Poller;
+use crate::Error::*;
+
+/// Reads items from a byte stream, made by [`poll_reader`]
+///
+/// At the end of the input it closes its channel, so the stages after it finish the
+/// items already sent and then stop too, which is how a ppio filter exits cleanly.
+pub struct Reader<R, T, C> {
+    reader: R,
+    framing: Framing,
+    codec: C,
+    max_frame: usize,
+    _item: PhantomData<fn() -> T>,
+}
+
+/// Reads `reader` as `framing` frames, decoded with `codec`
+pub fn poll_reader<R, T, C>(reader: R, framing: Framing, codec: C) -> (Poller<Reader<R, T, C>>, Receiver<T>)
+where
+    R: AsyncRead + Unpin + Send,
+    T: Send,
+    C: Codec<T> + Send,
+{
+    let (outlet, rx) = Outlet::new(Channel::bounded(1));
+    let reader = Reader { reader, framing, codec, max_frame: MAX_FRAME, _item: PhantomData };
+
+    (Poller::new(reader, outlet), rx)
+}
+
+impl<R, T, C> Poller<Reader<R, T, C>>
+where
+    R: AsyncRead + Unpin + Send,
+    T: Send,
+    C: Codec<T> + Send,
+{
+    /// Longest frame to read, a longer one fails the stage, 8 MiB by default
+    pub fn max_frame(self, bytes: usize) -> Self {
+        self.map_poller(|reader| Reader { max_frame: bytes, ..reader })
+    }
+}
+
+/// Reads the lines of `reader`
+pub fn poll_reader_lines<R: AsyncRead + Unpin + Send>(reader: R) -> (Poller<Reader<R, String, Text>>, Receiver<String>) {
+    poll_reader(reader, Framing::Lines, Text)
+}
+
+/// Reads the lines of stdin
+pub fn poll_stdin_lines() -> (Poller<Reader<Stdin, String, Text>>, Receiver<String>) {
+    poll_reader_lines(tokio::io::stdin())
+}
+
+impl<R, T, C> Poll for Reader<R, T, C>
+where
+    R: AsyncRead + Unpin + Send,
+    T: Send,
+    C: Codec<T> + Send,
+{
+    type Item = T;
+
+    async fn poll(&mut self, tx: Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut pending = Vec::new();
+        let mut chunk = vec![0; 64 << 10];
+
+        loop {
+            let n = self.reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+
+            pending.extend_from_slice(&chunk[..n]);
+
+            let mut used = 0;
+            while let Some((frame, len)) = self.framing.split(&pending[used..], self.max_frame)? {
+                let item = self.codec.decode(frame).context("decoding item")?;
+                tx.send(item).await.map_err(|_| Internal(SendError(()).into()))?;
+                used += len;
+            }
+
+            pending.drain(..used);
+        }
+
+        if let Some(frame) = self.framing.finish(&pending)? {
+            let item = self.codec.decode(frame).context("decoding item")?;
+            tx.send(item).await.map_err(|_| Internal(SendError(()).into()))?;
+        }
+
+        Err(Internal(anyhow!("end of input")).into())
+    }
+}
+
+/// Writes items to a byte stream, flushing after each one
+pub struct WriterPusher<W, T = String, C = Text> {
+    writer: W,
+    framing: Framing,
+    codec: C,
+    _item: PhantomData<fn(T)>,
+}
+
+/// Writes items to stdout, see [`WriterPusher`]
+pub type StdoutPusher<T = String, C = Text> = WriterPusher<Stdout, T, C>;
+
+impl<W: AsyncWrite + Unpin> WriterPusher<W> {
+    /// Writes every string on its own line
+    pub fn new(writer: W) -> Self {
+        Self::with_codec(writer, Framing::Lines, Text)
+    }
+}
+
+impl WriterPusher<Stdout> {
+    /// Writes every string to stdout on its own line
+    pub fn stdout() -> Self {
+        Self::new(tokio::io::stdout())
+    }
+}
+
+impl<W: AsyncWrite + Unpin, T, C: Codec<T>> WriterPusher<W, T, C> {
+    /// Writes items encoded with `codec`, as `framing` frames
+    pub fn with_codec(writer: W, framing: Framing, codec: C) -> Self {
+        Self { writer, framing, codec, _item: PhantomData }
+    }
+}
+
+impl<W, T, C> Push<T> for WriterPusher<W, T, C>
+where
+    W: AsyncWrite + Unpin + Send,
+    T: Send + 'static,
+    C: Codec<T> + Send,
+{
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        let bytes = self.codec.encode(&item)?;
+
+        match self.framing {
+            Framing::Lines => {
+                self.writer.write_all(&bytes).await?;
+                self.writer.write_all(b"\n").await?;
+            },
+            Framing::LengthDelimited => {
+                self.writer.write_u32(bytes.len() as u32).await?;
+                self.writer.write_all(&bytes).await?;
+            },
+        }
+
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use std::convert::Infallible;
+use std::io::{ErrorKind, SeekFrom};
+use std::marker::PhantomData;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::channel;
+use crate::codec::{Codec, Framing, Text, MAX_FRAME};
+use crate::io::Poll;
+
+/// Follows a file like `tail -F`, emitting every line or record appended to it
+///
+/// A file that shrinks is read again from the start, and when the path points at a
+/// new file (rotation by rename) the old one is read to its end before moving over.
+/// A trailing partial frame waits until it's complete, and a frame that fails to decode
+/// is logged and skipped, while one longer than [`FileTail::max_frame`] fails the stage.
+/// With an offset file, the inode and offset are saved after every batch of items, and a
+/// restart picks up from there.
+pub struct FileTail<T = String, C = Text> {
+    path: PathBuf,
+    framing: Framing,
+    codec: C,
+    max_frame: usize,
+    offsets: Option<PathBuf>,
+    interval: Duration,
+    from_end: bool,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl FileTail {
+    /// Follows the lines of `path`
+    pub fn lines(path: impl Into<PathBuf>) -> Self {
+        Self::records(path, Framing::Lines, Text)
+    }
+}
+
+impl<T, C: Codec<T>> FileTail<T, C> {
+    /// Follows `framing` frames of `path`, decoded with `codec`
+    pub fn records(path: impl Into<PathBuf>, framing: Framing, codec: C) -> Self {
+        Self {
+            path: path.into(),
+            framing,
+            codec,
+            max_frame: MAX_FRAME,
+            offsets: None,
+            interval: Duration::from_millis(250),
+            from_end: false,
+            _item: PhantomData,
+        }
+    }
+
+    /// Where to keep the inode and offset between runs
+    pub fn offset_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.offsets = Some(path.into());
+        self
+    }
+
+    /// Longest frame to read, 8 MiB by default
+    pub fn max_frame(mut self, bytes: usize) -> Self {
+        self.max_frame = bytes;
+        self
+    }
+
+    /// How often to look for new data, 250ms by default
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Skips what's already in the file when there is no saved offset, like `tail -f`
+    ///
+    /// Only the file found on start is skipped, the ones rotated in later are read whole.
+    pub fn from_end(mut self) -> Self {
+        self.from_end = true;
+        self
+    }
+
+    async fn load(&self) -> anyhow::Result<Option<(u64, u64)>> {
+        let Some(path) = &self.offsets else {
+            return Ok(None);
+        };
+
+        match fs::read(path).await {
+            Ok(bytes) if bytes.len() == 16 => {
+                let ino = u64::from_le_bytes(bytes[..8].try_into()?);
+                let offset = u64::from_le_bytes(bytes[8..].try_into()?);
+
+                Ok(Some((ino, offset)))
+            },
+            Ok(_) => anyhow::bail!("{} is not an offset file", path.display()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, ino: u64, offset: u64) -> anyhow::Result<()> {
+        if let Some(path) = &self.offsets {
+            let tmp = path.with_extension("tmp");
+            let bytes: Vec<u8> = ino.to_le_bytes().into_iter().chain(offset.to_le_bytes()).collect();
+
+            fs::write(&tmp, bytes).await?;
+            fs::rename(tmp, path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The file being read
+struct Open {
+    file: File,
+    ino: u64,
+    /// end of the last whole frame
+    offset: u64,
+    pending: Vec<u8>,
+    rotated: bool,
+}
+
+impl<T: Send + 'static, C: Codec<T> + Send + Sync> Poll for FileTail<T, C> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let mut saved = self.load().await?;
+        let mut open: Option<Open> = None;
+        let mut chunk = vec![0; 64 << 10];
+        let mut from_end = self.from_end;
+
+        loop {
+            if open.is_none() {
+                let opened = File::open(&self.path).await;
+                let skip = std::mem::take(&mut from_end);
+
+                match opened {
+                    Ok(mut file) => {
+                        let meta = file.metadata().await?;
+                        let offset = match saved.take() {
+                            Some((ino, offset)) if ino == meta.ino() && offset <= meta.len() => offset,
+                            // rotated or truncated while we were gone
+                            Some(_) => 0,
+                            None if skip => meta.len(),
+                            None => 0,
+                        };
+
+                        file.seek(SeekFrom::Start(offset)).await?;
+                        open = Some(Open { file, ino: meta.ino(), offset, pending: Vec::new(), rotated: false });
+                    },
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        tokio::time::sleep(self.interval).await;
+                        continue;
+                    },
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            let current = open.as_mut().unwrap();
+            let start = current.offset;
+
+            loop {
+                let n = current.file.read(&mut chunk).await?;
+                if n == 0 {
+                    break;
+                }
+
+                current.pending.extend_from_slice(&chunk[..n]);
+
+                let mut used = 0;
+                while let Some((frame, len)) = self.framing.split(&current.pending[used..], self.max_frame)? {
+                    let at = current.offset + used as u64;
+                    used += len;
+
+                    // a bad frame would come back on every restart, so it's passed over
+                    match self.codec.decode(frame) {
+                        Ok(item) => tx.send(item).await.map_err(|_| channel::SendError(()))?,
+                        Err(e) => log::warn!("skipping the frame at {at} in {}: {e:#}", self.path.display()),
+                    }
+                }
+
+                current.pending.drain(..used);
+                current.offset += used as u64;
+            }
+
+            if current.offset != start {
+                self.save(current.ino, current.offset).await?;
+            }
+
+            // at the end of the file, see whether it was truncated or replaced
+            let len = match fs::metadata(&self.path).await {
+                Ok(meta) if meta.ino() == current.ino => Some(meta.len()),
+                Ok(_) => None,
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+
+            match len {
+                // one more pass over the old file, for what was written just before the rename
+                None if !current.rotated => {
+                    current.rotated = true;
+                    continue;
+                },
+                None => {
+                    open = None;
+                    continue;
+                },
+                Some(len) if len < current.offset + current.pending.len() as u64 => {
+                    current.file.seek(SeekFrom::Start(0)).await?;
+                    current.offset = 0;
+                    current.pending.clear();
+                    continue;
+                },
+                Some(_) => {},
+            }
+
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+}
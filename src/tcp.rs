@@ -0,0 +1,127 @@
+use std::convert::Infallible;
+use std::io;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::channel;
+use crate::codec::Codec;
+use crate::io::{Poll, Push, State};
+use crate::link::{self, Accept, Connect, Dialer};
+
+/// Receives items sent by [`TcpPusher`]s, from any number of connections
+///
+/// Senders get more credit as items are taken by the poller's channel, give it a
+/// bounded [`Channel`](crate::channel::Channel) for a slow consumer to hold them up.
+/// As a [`State`] it takes a new address to listen on.
+pub struct TcpPoller<T, C> {
+    addr: String,
+    codec: C,
+    window: u32,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T, C: Codec<T>> TcpPoller<T, C> {
+    /// Listens on `addr` once polled, items are decoded with `codec`
+    pub fn bind(addr: impl Into<String>, codec: C) -> Self {
+        Self {
+            addr: addr.into(),
+            codec,
+            window: 64,
+            _item: PhantomData,
+        }
+    }
+
+    /// How many items each sender may have in flight, 64 by default
+    pub fn window(mut self, items: u32) -> Self {
+        self.window = items.max(1);
+        self
+    }
+}
+
+impl<T, C> State<String> for TcpPoller<T, C> {
+    fn update(&mut self, addr: String) {
+        self.addr = addr;
+    }
+}
+
+impl<T: Send + 'static, C: Codec<T> + Send + Sync> Poll for TcpPoller<T, C> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let listener = TcpListener::bind(&self.addr).await?;
+        link::serve_all(&listener, &self.codec, &tx, self.window).await
+    }
+}
+
+impl Accept for TcpListener {
+    type Read = OwnedReadHalf;
+    type Write = OwnedWriteHalf;
+
+    async fn accept(&self) -> io::Result<(Self::Read, Self::Write)> {
+        Ok(TcpListener::accept(self).await?.0.into_split())
+    }
+}
+
+/// Connects to `addr` for a [`Dialer`]
+pub(crate) struct TcpConnect(String);
+
+impl Connect for TcpConnect {
+    type Read = OwnedReadHalf;
+    type Write = OwnedWriteHalf;
+
+    async fn connect(&self) -> io::Result<(Self::Read, Self::Write)> {
+        let stream = TcpStream::connect(&self.0).await?;
+        stream.set_nodelay(true)?;
+
+        Ok(stream.into_split())
+    }
+}
+
+/// Sends items to a [`TcpPoller`], reconnecting with backoff whenever the link drops
+///
+/// A push only finishes once the remote has room for the item, so a slow remote
+/// consumer holds up this pusher. Items stay with the pusher until the remote acks them,
+/// and those on a dropped link are sent again, so delivery is at least once: around a
+/// reconnect the remote may get an item twice. One the remote can't decode is sent again
+/// a few times, then dropped with an error logged.
+/// As a [`State`] it takes a new address and reconnects there for the next item.
+pub struct TcpPusher<T, C> {
+    dialer: Dialer<TcpConnect>,
+    codec: C,
+    _item: PhantomData<fn(T)>,
+}
+
+impl<T, C: Codec<T>> TcpPusher<T, C> {
+    /// Connects to `addr` on the first push, items are encoded with `codec`
+    pub fn connect(addr: impl Into<String>, codec: C) -> Self {
+        Self {
+            dialer: Dialer::new(TcpConnect(addr.into())),
+            codec,
+            _item: PhantomData,
+        }
+    }
+
+    /// Delay before reconnecting, doubling from `min` up to `max`, 100ms to 10s by default
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.dialer.backoff(min, max);
+        self
+    }
+}
+
+impl<T, C> State<String> for TcpPusher<T, C> {
+    fn update(&mut self, addr: String) {
+        self.dialer.redial(TcpConnect(addr));
+    }
+}
+
+impl<T: Send, C: Codec<T> + Send> Push<T> for TcpPusher<T, C> {
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        let bytes = self.codec.encode(&item)?;
+        self.dialer.send(bytes).await;
+
+        Ok(())
+    }
+}
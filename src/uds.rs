@@ -0,0 +1,149 @@
+use std::convert::Infallible;
+use std::io::{self, ErrorKind};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::channel;
+use crate::codec::Codec;
+use crate::io::{Poll, Push, State};
+use crate::link::{self, Accept, Connect, Dialer};
+
+/// Receives items sent by [`UdsPusher`]s on the same host, with the same framing and flow
+/// control as the TCP transport
+///
+/// A socket file left behind by a process that is gone is replaced, one that still has a
+/// listener fails the poll. The socket file is removed again when the poll stops. As a
+/// [`State`] it takes a new path, so the path can come from configuration updates.
+pub struct UdsPoller<T, C> {
+    path: PathBuf,
+    codec: C,
+    window: u32,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T, C: Codec<T>> UdsPoller<T, C> {
+    /// Listens on `path` once polled, items are decoded with `codec`
+    pub fn bind(path: impl Into<PathBuf>, codec: C) -> Self {
+        Self {
+            path: path.into(),
+            codec,
+            window: 64,
+            _item: PhantomData,
+        }
+    }
+
+    /// How many items each sender may have in flight, 64 by default
+    pub fn window(mut self, items: u32) -> Self {
+        self.window = items.max(1);
+        self
+    }
+}
+
+impl<T, C> State<PathBuf> for UdsPoller<T, C> {
+    fn update(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+}
+
+/// Removes the socket file along with the listener
+struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Listener {
+    async fn bind(path: &Path) -> io::Result<Self> {
+        match UnixStream::connect(path).await {
+            Ok(_) => return Err(io::Error::new(ErrorKind::AddrInUse, format!("{} is in use", path.display()))),
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(_) => {},
+        }
+
+        Ok(Self {
+            listener: UnixListener::bind(path)?,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl Accept for Listener {
+    type Read = OwnedReadHalf;
+    type Write = OwnedWriteHalf;
+
+    async fn accept(&self) -> io::Result<(Self::Read, Self::Write)> {
+        Ok(self.listener.accept().await?.0.into_split())
+    }
+}
+
+impl<T: Send + 'static, C: Codec<T> + Send + Sync> Poll for UdsPoller<T, C> {
+    type Item = T;
+
+    async fn poll(&mut self, tx: channel::Sender<Self::Item>) -> anyhow::Result<Infallible> {
+        let listener = Listener::bind(&self.path).await?;
+        link::serve_all(&listener, &self.codec, &tx, self.window).await
+    }
+}
+
+/// Connects to `path` for a [`Dialer`]
+pub(crate) struct UdsConnect(PathBuf);
+
+impl Connect for UdsConnect {
+    type Read = OwnedReadHalf;
+    type Write = OwnedWriteHalf;
+
+    async fn connect(&self) -> io::Result<(Self::Read, Self::Write)> {
+        Ok(UnixStream::connect(&self.0).await?.into_split())
+    }
+}
+
+/// Sends items to a [`UdsPoller`], reconnecting with backoff like the TCP transport,
+/// and delivering at least once like it too
+///
+/// As a [`State`] it takes a new path and reconnects there for the next item.
+pub struct UdsPusher<T, C> {
+    dialer: Dialer<UdsConnect>,
+    codec: C,
+    _item: PhantomData<fn(T)>,
+}
+
+impl<T, C: Codec<T>> UdsPusher<T, C> {
+    /// Connects to `path` on the first push, items are encoded with `codec`
+    pub fn connect(path: impl Into<PathBuf>, codec: C) -> Self {
+        Self {
+            dialer: Dialer::new(UdsConnect(path.into())),
+            codec,
+            _item: PhantomData,
+        }
+    }
+
+    /// Delay before reconnecting, doubling from `min` up to `max`, 100ms to 10s by default
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.dialer.backoff(min, max);
+        self
+    }
+}
+
+impl<T, C> State<PathBuf> for UdsPusher<T, C> {
+    fn update(&mut self, path: PathBuf) {
+        self.dialer.redial(UdsConnect(path));
+    }
+}
+
+impl<T: Send, C: Codec<T> + Send> Push<T> for UdsPusher<T, C> {
+    async fn push(&mut self, item: T) -> anyhow::Result<()> {
+        let bytes = self.codec.encode(&item)?;
+        self.dialer.send(bytes).await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::{task, task::Poll::*};
+
+use futures::future::BoxFuture;
+
+use crate::io::{AsyncState, Rejected};
+use crate::state::Ack;
+use crate::Error::*;
+use crate::util::as_static_mut;
+
+/// Drives [`AsyncState::update`] for the stateful stages, one update at a time
+pub(crate) struct Updater<S> {
+    fut: Option<BoxFuture<'static, anyhow::Result<()>>>,
+    policy: Rejected,
+    retain: Option<fn(&S) -> S>,
+    ack: Option<Ack>,
+    applying: Option<S>,
+    previous: Option<S>,
+    reverting: bool,
+}
+
+impl<S: 'static> Updater<S> {
+    pub fn new() -> Self {
+        Self {
+            fut: None,
+            policy: Rejected::default(),
+            retain: None,
+            ack: None,
+            applying: None,
+            previous: None,
+            reverting: false,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: Rejected)
+    where
+        S: Clone,
+    {
+        self.policy = policy;
+        self.retain = (policy == Rejected::KeepPrevious).then_some(S::clone);
+    }
+
+    /// The caller must make sure nothing else borrows `target` until the update resolves
+    pub fn start<A: AsyncState<S> + 'static>(&mut self, target: &mut A, state: S, ack: Option<Ack>) {
+        self.ack = ack;
+        self.applying = self.retain.map(|clone| clone(&state));
+
+        let target = unsafe { as_static_mut(target) };
+        self.fut = Some(Box::pin(target.update(state)));
+    }
+
+    /// Ready once no update is in flight
+    pub fn poll_update<A: AsyncState<S> + 'static>(
+        &mut self,
+        target: &mut A,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<(), crate::Error>> {
+        while let Some(fut) = self.fut.as_mut() {
+            let res = futures::ready!(fut.as_mut().poll(cx));
+            self.fut = None;
+
+            let reverting = std::mem::take(&mut self.reverting);
+
+            if let Some(ack) = self.ack.take() {
+                let _ = ack.send(match &res {
+                    Ok(()) => Ok(()),
+                    Err(e) => Err(anyhow::anyhow!("state rejected: {e:#}")),
+                });
+            }
+
+            match res {
+                Ok(()) if reverting => (),
+                Ok(()) => self.previous = self.applying.take(),
+                Err(e) if reverting => return Ready(Err(User(e))),
+                Err(e) => match self.policy {
+                    Rejected::Ignore => log::warn!("ignoring a rejected state: {e:#}"),
+                    Rejected::Fail => return Ready(Err(User(e))),
+                    Rejected::KeepPrevious => {
+                        self.applying = None;
+
+                        if let (Some(previous), Some(clone)) = (&self.previous, self.retain) {
+                            let target = unsafe { as_static_mut(target) };
+
+                            self.fut = Some(Box::pin(target.update(clone(previous))));
+                            self.reverting = true;
+                        }
+                    }
+                },
+            }
+        }
+
+        Ready(Ok(()))
+    }
+}